@@ -0,0 +1,172 @@
+use crate::contact_repo::MAX_PAGE_SIZE;
+
+const DEFAULT_DATABASE_URL: &str = "sqlite://contacts.db?mode=rwc";
+const DEFAULT_FAKE_CONTACTS: u32 = 100;
+const DEFAULT_ARCHIVE_STEPS: u8 = 10;
+const DEFAULT_ARCHIVE_FILE_NAME: &str = "archive.{ext}";
+const DEFAULT_PAGE_SIZE: u32 = 10;
+const DEFAULT_VALIDATION_RATE_LIMIT: u32 = 120;
+const DEFAULT_IMPORT_ROW_LIMIT: u32 = 1000;
+
+/// Everything [`crate::build_app`] needs to know to assemble the app.
+pub struct AppConfig {
+    pub environment: Environment,
+    /// Whether the app can only be reached through a reverse proxy, so that
+    /// the `X-Forwarded-Proto` header it sets can be trusted. Plain HTTP
+    /// requests are only redirected to HTTPS in production with this, see
+    /// [`crate::https_redirect::redirect_to_https`].
+    pub behind_proxy: bool,
+    pub database_url: String,
+    /// How many fake contacts an empty database is filled with.
+    pub fake_contacts: u32,
+    /// How many times the archive progress is updated during a run.
+    pub archive_steps: u8,
+    /// See [`crate::contacts_archiver::resolve_file_name`].
+    pub archive_file_name: String,
+    /// How phone numbers are shown.
+    pub phone_locale: PhoneLocale,
+    /// Whether phone numbers and emails are partly hidden in the contact list.
+    /// The contact's own page always shows them in full.
+    pub mask_contact_details: bool,
+    /// How many contacts a page holds, unless asked otherwise.
+    pub page_size: u32,
+    /// How many inline validation requests a client may make per minute.
+    pub validation_rate_limit: u32,
+    /// How many rows an import may have. Larger ones are refused whole.
+    pub import_row_limit: u32,
+    /// Whether the contact count takes a while, to show off its loading
+    /// indicator as the book does.
+    pub demo_slow_count: bool,
+    /// Whether the queries to the database are counted, for tests to check
+    /// how many a request makes. See [`crate::AppState::query_count`].
+    pub count_queries: bool,
+}
+impl AppConfig {
+    /// Reads the database from `DATABASE_URL`, defaulting to `contacts.db` in
+    /// the working directory.
+    ///
+    /// With the `--seed` flag or the `SEED` variable set, an empty database is
+    /// filled with fake contacts, and the database defaults to an in-memory
+    /// one instead.
+    ///
+    /// With the `BEHIND_PROXY` variable set, the app trusts the
+    /// `X-Forwarded-Proto` header.
+    ///
+    /// With the `MASK_CONTACT_DETAILS` variable set, phone numbers and emails
+    /// are partly hidden in the contact list.
+    ///
+    /// How many times the archive progress is updated during a run is read
+    /// from `ARCHIVE_STEPS`, from 1 up to 100.
+    ///
+    /// The name of the downloaded archive is read from `ARCHIVE_FILE_NAME`,
+    /// e.g. `contacts-{date}.{ext}`.
+    ///
+    /// The default page size is read from `PAGE_SIZE`, up to
+    /// [`MAX_PAGE_SIZE`].
+    ///
+    /// The number of inline validation requests a client may make per minute
+    /// is read from `VALIDATION_RATE_LIMIT`.
+    ///
+    /// The number of rows an import may have is read from
+    /// `IMPORT_ROW_LIMIT`.
+    ///
+    /// With the `DEMO_SLOW_COUNT` variable set, the contact count is delayed.
+    pub fn from_env() -> Self {
+        let seed =
+            std::env::args().any(|arg| arg == "--seed") || std::env::var_os("SEED").is_some();
+        let database_url = std::env::var("DATABASE_URL").unwrap_or_else(|_| {
+            if seed {
+                ":memory:"
+            } else {
+                DEFAULT_DATABASE_URL
+            }
+            .to_string()
+        });
+
+        Self {
+            environment: Environment::from_env(),
+            behind_proxy: std::env::var_os("BEHIND_PROXY").is_some(),
+            database_url,
+            fake_contacts: if seed { DEFAULT_FAKE_CONTACTS } else { 0 },
+            archive_steps: std::env::var("ARCHIVE_STEPS")
+                .ok()
+                .and_then(|steps| steps.parse().ok())
+                .unwrap_or(DEFAULT_ARCHIVE_STEPS)
+                .clamp(1, 100),
+            archive_file_name: std::env::var("ARCHIVE_FILE_NAME")
+                .unwrap_or_else(|_| DEFAULT_ARCHIVE_FILE_NAME.to_string()),
+            page_size: std::env::var("PAGE_SIZE")
+                .ok()
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(DEFAULT_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+            phone_locale: PhoneLocale::from_env(),
+            mask_contact_details: std::env::var_os("MASK_CONTACT_DETAILS").is_some(),
+            validation_rate_limit: std::env::var("VALIDATION_RATE_LIMIT")
+                .ok()
+                .and_then(|limit| limit.parse().ok())
+                .unwrap_or(DEFAULT_VALIDATION_RATE_LIMIT),
+            import_row_limit: std::env::var("IMPORT_ROW_LIMIT")
+                .ok()
+                .and_then(|limit| limit.parse().ok())
+                .unwrap_or(DEFAULT_IMPORT_ROW_LIMIT),
+            demo_slow_count: std::env::var_os("DEMO_SLOW_COUNT").is_some(),
+            count_queries: false,
+        }
+    }
+}
+
+/// How phone numbers are grouped when shown. They are stored as entered, or
+/// normalized, either way.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum PhoneLocale {
+    /// `(555) 123-4567`, or `+1 (555) 123-4567` with the country code.
+    #[default]
+    NorthAmerica,
+    /// As entered, as there is no one way of grouping the digits of numbers
+    /// from everywhere.
+    International,
+}
+impl PhoneLocale {
+    /// Reads the locale from `PHONE_LOCALE`, defaulting to
+    /// [`PhoneLocale::NorthAmerica`].
+    pub fn from_env() -> Self {
+        match std::env::var("PHONE_LOCALE").as_deref() {
+            Ok("international") => Self::International,
+            _ => Self::NorthAmerica,
+        }
+    }
+}
+
+/// Where the app is running. Security features that only make sense behind
+/// TLS are switched on and off together by this.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Environment {
+    /// Plain HTTP on localhost.
+    #[default]
+    Development,
+    /// Served over HTTPS.
+    Production,
+}
+impl Environment {
+    /// Reads the environment from `APP_ENV`, defaulting to
+    /// [`Environment::Development`].
+    pub fn from_env() -> Self {
+        match std::env::var("APP_ENV").as_deref() {
+            Ok("production") => Self::Production,
+            _ => Self::Development,
+        }
+    }
+
+    pub fn use_secure_cookies(&self) -> bool {
+        *self == Self::Production
+    }
+
+    pub fn redirect_to_https(&self) -> bool {
+        *self == Self::Production
+    }
+
+    pub fn upgrade_insecure_requests(&self) -> bool {
+        *self == Self::Production
+    }
+}
@@ -1,8 +1,19 @@
+use std::collections::HashSet;
+
+use md5::{Digest, Md5};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
 use serde::Serialize;
 use sqlx::FromRow;
 use typed_builder::TypedBuilder;
 
-#[derive(Default)]
+use crate::config::PhoneLocale;
+
+/// What [`Contact::last_name_initial`] is for last names not starting with a
+/// latin letter.
+pub const OTHER_INITIAL: char = '#';
+
+#[derive(Default, Serialize)]
 pub struct ContactErrors {
     pub first: Option<String>,
     pub last: Option<String>,
@@ -10,6 +21,21 @@ pub struct ContactErrors {
     pub email: Option<String>,
 }
 
+impl ContactErrors {
+    pub fn messages(&self) -> impl Iterator<Item = &str> {
+        [&self.first, &self.last, &self.phone, &self.email]
+            .into_iter()
+            .filter_map(|err| err.as_deref())
+    }
+}
+
+/// Things that are likely mistakes, but that don't stop the contact from being
+/// saved.
+#[derive(Default)]
+pub struct ContactWarnings {
+    pub name: Option<String>,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Serialize)]
 #[serde(transparent)]
 pub struct ContactId(u32);
@@ -25,16 +51,18 @@ impl ContactId {
 
 #[derive(Clone, TypedBuilder, Serialize)]
 pub struct Contact {
-    id: ContactId,
+    /// `None` until the contact is saved.
+    #[builder(default)]
+    id: Option<ContactId>,
     first: String,
     last: String,
     phone: String,
     email: String,
 }
 impl Contact {
-    pub fn new_fake(id: ContactId) -> Self {
+    pub fn new_fake() -> Self {
         Self {
-            id,
+            id: None,
             first: fakeit::name::first(),
             last: fakeit::name::last(),
             phone: fakeit::contact::phone(),
@@ -43,16 +71,40 @@ impl Contact {
     }
 
     pub fn match_text(&self, str: &str) -> bool {
-        let str = str.to_lowercase();
+        let str = fold_for_search(str);
 
-        for item in [&self.first, &self.last] {
-            if item.to_lowercase().contains(&str) {
+        for item in [&self.first, &self.last, &self.phone, &self.email] {
+            if fold_for_search(item).contains(&str) {
                 return true;
             }
         }
         false
     }
 
+    /// The first letter of the last name, upper-cased and without accents by
+    /// [`fold_for_search`], so that "Álvarez" is under A. [`OTHER_INITIAL`]
+    /// if it does not start with a latin letter.
+    pub fn last_name_initial(&self) -> char {
+        match fold_for_search(&self.last).chars().next() {
+            Some(c) if c.is_ascii_alphabetic() => c.to_ascii_uppercase(),
+            _ => OTHER_INITIAL,
+        }
+    }
+
+    /// How similar `str` is to the name of the contact, from `0.0` to `1.0`.
+    /// Trigrams alone miss typos in short names, as one swap changes most of
+    /// their trigrams, so each name is also compared by [`typo_similarity`].
+    pub fn similarity(&self, str: &str) -> f32 {
+        let full_name = format!("{} {}", self.first, self.last);
+        let trigram = [&self.first, &self.last, &full_name]
+            .into_iter()
+            .map(|item| trigram_similarity(item, str));
+        let typo = [&self.first, &self.last]
+            .into_iter()
+            .map(|item| typo_similarity(item, str));
+        trigram.chain(typo).fold(0.0, f32::max)
+    }
+
     pub fn validate_email(email: &str) -> Option<String> {
         if email.is_empty() {
             Some("Email Required".to_string())
@@ -63,14 +115,50 @@ impl Contact {
         }
     }
 
+    /// Phone numbers are optional. A given one may be in any common notation,
+    /// with an optional leading `+` and an optional extension.
+    ///
+    /// NOTE: `validator::validate_phone` is not used because it rejects
+    /// numbers without a country code.
+    pub fn validate_phone(phone: &str) -> Option<String> {
+        let phone = phone.trim();
+        if phone.is_empty() {
+            return None;
+        }
+
+        let lowercase = phone.to_lowercase();
+        let (number, extension) = match lowercase
+            .split_once("ext.")
+            .or_else(|| lowercase.split_once("ext"))
+            .or_else(|| lowercase.split_once('x'))
+        {
+            Some((number, extension)) => (number, Some(extension.trim())),
+            None => (lowercase.as_str(), None),
+        };
+        let number = number.trim();
+        let number = number.strip_prefix('+').unwrap_or(number);
+
+        let is_number_plausible = number
+            .chars()
+            .all(|c| c.is_ascii_digit() || " -.()".contains(c))
+            && (7..=15).contains(&number.chars().filter(char::is_ascii_digit).count());
+        let is_extension_plausible = extension.is_none_or(|extension| {
+            !extension.is_empty()
+                && extension.len() <= 6
+                && extension.chars().all(|c| c.is_ascii_digit())
+        });
+
+        if is_number_plausible && is_extension_plausible {
+            None
+        } else {
+            Some("Phone Not Valid".to_string())
+        }
+    }
+
     pub fn validate(&self) -> Result<(), ContactErrors> {
         let err_email = Self::validate_email(&self.email);
 
-        let err_phone = /*if !self.phone.is_empty() && !validator::validate_phone(&self.phone) {
-            Some("Phone Not Valid".to_string())
-        } else {
-            None
-        };*/ None;
+        let err_phone = Self::validate_phone(&self.phone);
 
         if err_email.is_some() || err_phone.is_some() {
             Err(ContactErrors {
@@ -83,7 +171,197 @@ impl Contact {
         }
     }
 
-    pub fn id(&self) -> ContactId {
+    pub fn warnings(&self) -> ContactWarnings {
+        let name = if self.suggest_name_split().is_some() {
+            Some("Looks Like a Full Name".to_string())
+        } else if !self.first.trim().is_empty()
+            && self.first.trim().to_lowercase() == self.last.trim().to_lowercase()
+        {
+            Some("First and Last Name Are the Same".to_string())
+        } else {
+            None
+        };
+
+        ContactWarnings { name }
+    }
+
+    /// If a whole name seems to be pasted into one of the name fields with the
+    /// other left empty, splits it into the first and the last name, taking
+    /// the last word as the last name.
+    pub fn suggest_name_split(&self) -> Option<(String, String)> {
+        let full_name = match (self.first.trim(), self.last.trim()) {
+            (full_name, "") | ("", full_name) => full_name,
+            _ => return None,
+        };
+        let (first, last) = full_name.rsplit_once(char::is_whitespace)?;
+
+        Some((first.trim_end().to_string(), last.to_string()))
+    }
+
+    /// The phone number as digits with a leading `+` and the country code,
+    /// e.g. `+15551234567`, the way it is saved. Ten digits are taken as a
+    /// North American number. Numbers this can't be sure about, like those
+    /// with an extension or without a country code, are returned unchanged,
+    /// as is an empty one.
+    ///
+    /// This is independent of [`Self::validate_phone`], which accepts more.
+    pub fn normalized_phone(&self) -> String {
+        let phone = self.phone.trim();
+        let (has_plus, number) = match phone.strip_prefix('+') {
+            Some(number) => (true, number),
+            None => (false, phone),
+        };
+        if !number
+            .chars()
+            .all(|c| c.is_ascii_digit() || " -.()".contains(c))
+        {
+            return self.phone.clone();
+        }
+        let digits: String = number.chars().filter(char::is_ascii_digit).collect();
+
+        match digits.len() {
+            8..=15 if has_plus => format!("+{}", digits),
+            10 => format!("+1{}", digits),
+            11 if digits.starts_with('1') => format!("+{}", digits),
+            _ => self.phone.clone(),
+        }
+    }
+
+    /// Replaces the phone number with [`Self::normalized_phone`].
+    pub fn normalize_phone(&mut self) {
+        self.phone = self.normalized_phone();
+    }
+
+    /// Groups the digits of a North American number for display, e.g.
+    /// `(555) 123-4567`, in [`PhoneLocale::NorthAmerica`]. Anything else is
+    /// returned unchanged.
+    pub fn formatted_phone(&self, locale: PhoneLocale) -> String {
+        if locale != PhoneLocale::NorthAmerica {
+            return self.phone.clone();
+        }
+        let is_plain = self
+            .phone
+            .chars()
+            .all(|c| c.is_ascii_digit() || " +-.()".contains(c));
+        let digits: String = self.phone.chars().filter(char::is_ascii_digit).collect();
+
+        match digits.len() {
+            10 if is_plain && !self.phone.starts_with('+') => {
+                format!("({}) {}-{}", &digits[..3], &digits[3..6], &digits[6..])
+            }
+            11 if is_plain && digits.starts_with('1') => {
+                format!("+1 ({}) {}-{}", &digits[1..4], &digits[4..7], &digits[7..])
+            }
+            _ => self.phone.clone(),
+        }
+    }
+
+    /// [`Self::formatted_phone`] with all but the last four digits of the
+    /// number hidden, e.g. `(***) ***-4567`, or `***-***-4567 x89` with an
+    /// extension.
+    pub fn masked_phone(&self, locale: PhoneLocale) -> String {
+        let formatted = self.formatted_phone(locale);
+        // The extension starts at the first letter, as in `x89` or `ext. 89`,
+        // and is left as is.
+        let (number, extension) = formatted.split_at(
+            formatted
+                .find(|c: char| c.is_ascii_alphabetic())
+                .unwrap_or(formatted.len()),
+        );
+        let digits = number.chars().filter(char::is_ascii_digit).count();
+
+        let mut seen_digits = 0;
+        let mut masked: String = number
+            .chars()
+            .map(|c| {
+                if !c.is_ascii_digit() {
+                    return c;
+                }
+                seen_digits += 1;
+                if seen_digits + 4 > digits {
+                    c
+                } else {
+                    '*'
+                }
+            })
+            .collect();
+        masked.push_str(extension);
+        masked
+    }
+
+    /// The email with all but the first character of its local part hidden,
+    /// e.g. `j***@example.com`.
+    pub fn masked_email(&self) -> String {
+        match self.email.split_once('@') {
+            Some((local, domain)) => match local.chars().next() {
+                Some(first) => format!("{}***@{}", first, domain),
+                None => format!("***@{}", domain),
+            },
+            None => "***".to_string(),
+        }
+    }
+
+    /// The URL of the Gravatar for the email, `size` pixels wide and high.
+    /// Emails without one get a generated identicon.
+    pub fn gravatar_url(&self, size: u32) -> String {
+        let hash = Md5::digest(self.email.trim().to_lowercase().as_bytes());
+        format!(
+            "https://www.gravatar.com/avatar/{:x}?s={}&d=identicon",
+            hash, size
+        )
+    }
+
+    /// The contact as a vCard 4.0 (RFC 6350) entry, lines ending with CRLF.
+    pub fn to_vcard(&self) -> String {
+        let mut vcard = String::from("BEGIN:VCARD\r\nVERSION:4.0\r\n");
+        vcard += &format!(
+            "N:{};{};;;\r\n",
+            escape_vcard_text(&self.last),
+            escape_vcard_text(&self.first),
+        );
+        vcard += &format!(
+            "FN:{}\r\n",
+            escape_vcard_text(&format!("{} {}", self.first, self.last)),
+        );
+        if !self.phone.is_empty() {
+            vcard += &format!("TEL:{}\r\n", escape_vcard_text(&self.phone));
+        }
+        vcard += &format!("EMAIL:{}\r\n", escape_vcard_text(&self.email));
+        vcard += "END:VCARD\r\n";
+        vcard
+    }
+
+    /// Fills the fields this contact leaves empty from `other`. Returns the
+    /// labels of the fields both have different values for, beyond case and
+    /// surrounding spaces, where this contact's value is kept.
+    pub fn merge_from(&mut self, other: &Contact) -> Vec<&'static str> {
+        let mut conflicts = vec![];
+        for (label, field, other_field) in [
+            ("First Name", &mut self.first, &other.first),
+            ("Last Name", &mut self.last, &other.last),
+            ("Phone", &mut self.phone, &other.phone),
+            ("Email", &mut self.email, &other.email),
+        ] {
+            if field.trim().is_empty() {
+                field.clone_from(other_field);
+            } else if !other_field.trim().is_empty()
+                && field.trim().to_lowercase() != other_field.trim().to_lowercase()
+            {
+                conflicts.push(label);
+            }
+        }
+        conflicts
+    }
+
+    /// The contact as saved under `id`.
+    pub fn with_id(self, id: ContactId) -> Self {
+        Self {
+            id: Some(id),
+            ..self
+        }
+    }
+
+    pub fn id(&self) -> Option<ContactId> {
         self.id
     }
     pub fn first(&self) -> &str {
@@ -110,7 +388,7 @@ where
     /// See: <https://stackoverflow.com/a/66713961>.
     fn from_row(row: &'r R) -> sqlx::Result<Self> {
         Ok(Self {
-            id: ContactId::new(row.try_get("id")?),
+            id: Some(ContactId::new(row.try_get("id")?)),
             first: row.try_get("first")?,
             last: row.try_get("last")?,
             phone: row.try_get("phone")?,
@@ -118,3 +396,449 @@ where
         })
     }
 }
+
+/// How similar two words are by the number of typos between them, counting
+/// a swap of adjacent letters as one. Up to one typo per four letters is
+/// tolerated, so words shorter than that have none, and are `0.0` unless the
+/// same. Otherwise, the fewer typos the closer to `1.0`.
+fn typo_similarity(a: &str, b: &str) -> f32 {
+    let (a, b): (Vec<char>, Vec<char>) = (
+        a.trim().to_lowercase().chars().collect(),
+        b.trim().to_lowercase().chars().collect(),
+    );
+    let len = a.len().max(b.len());
+    if len == 0 {
+        return 0.0;
+    }
+    let typos = typo_count(&a, &b);
+    if typos > len / 4 {
+        return 0.0;
+    }
+    1.0 - typos as f32 / len as f32
+}
+
+/// The optimal string alignment distance between `a` and `b`: how many
+/// insertions, deletions, substitutions and swaps of adjacent characters
+/// turn one into the other, with no character edited twice.
+fn typo_count(a: &[char], b: &[char]) -> usize {
+    // `rows[2]` is the row of distances being filled, `rows[1]` the previous
+    // one and `rows[0]` the one before, for swaps.
+    let mut rows = [
+        vec![0; b.len() + 1],
+        vec![0; b.len() + 1],
+        (0..=b.len()).collect(),
+    ];
+    for i in 1..=a.len() {
+        rows.rotate_left(1);
+        rows[2][0] = i;
+        for j in 1..=b.len() {
+            let substitution = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (rows[1][j] + 1)
+                .min(rows[2][j - 1] + 1)
+                .min(rows[1][j - 1] + substitution);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[0][j - 2] + 1);
+            }
+            rows[2][j] = distance;
+        }
+    }
+    rows[2][b.len()]
+}
+
+/// Trigram similarity as `pg_trgm` computes it: the shared trigrams over all
+/// trigrams of the two strings, with each word padded by two spaces in front
+/// and one behind.
+fn trigram_similarity(a: &str, b: &str) -> f32 {
+    let (a, b) = (trigrams(a), trigrams(b));
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(&b).count() as f32 / union as f32
+}
+
+fn trigrams(str: &str) -> HashSet<[char; 3]> {
+    let mut trigrams = HashSet::new();
+    for word in str
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        let chars: Vec<char> = "  ".chars().chain(word.chars()).chain([' ']).collect();
+        for trigram in chars.windows(3) {
+            trigrams.insert([trigram[0], trigram[1], trigram[2]]);
+        }
+    }
+    trigrams
+}
+
+/// The digits of `q`, if it looks like (part of) a phone number, so that
+/// searching for "123-4567" finds "+15551234567".
+pub fn phone_digits_for_search(q: &str) -> Option<String> {
+    let q = q.trim();
+    let is_phone_like = q
+        .chars()
+        .all(|c| c.is_ascii_digit() || " +-.()".contains(c));
+    let digits: String = q.chars().filter(char::is_ascii_digit).collect();
+
+    (is_phone_like && !digits.is_empty()).then_some(digits)
+}
+
+/// Lower-cases `text` and strips its accents, so that searching for "jose"
+/// finds "José".
+pub fn fold_for_search(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Escapes the characters that have a meaning in vCard property values.
+fn escape_vcard_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ',' | ';' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_phone(phone: &str) -> Contact {
+        Contact::builder()
+            .first("Ada".to_string())
+            .last("Lovelace".to_string())
+            .phone(phone.to_string())
+            .email("ada@example.com".to_string())
+            .build()
+    }
+
+    #[test]
+    fn validate_phone_accepts_international_formats() {
+        for phone in [
+            "+44 20 7183 8750",
+            "+49 (0)30 1234567",
+            "+81-3-1234-5678",
+            "+861012345678",
+            "+1 (555) 123-4567",
+            "555.123.4567",
+        ] {
+            assert_eq!(Contact::validate_phone(phone), None, "{}", phone);
+        }
+    }
+
+    #[test]
+    fn validate_phone_accepts_extensions() {
+        for phone in [
+            "555-123-4567 x89",
+            "555-123-4567x89",
+            "555-123-4567 ext 89",
+            "555-123-4567 Ext. 123456",
+            "+44 20 7183 8750 ext. 12",
+        ] {
+            assert_eq!(Contact::validate_phone(phone), None, "{}", phone);
+        }
+    }
+
+    #[test]
+    fn validate_phone_accepts_no_phone() {
+        assert_eq!(Contact::validate_phone(""), None);
+        assert_eq!(Contact::validate_phone("   "), None);
+    }
+
+    #[test]
+    fn validate_phone_rejects_invalid_input() {
+        for phone in [
+            "call me",
+            "555-CALL-NOW",
+            "123456",
+            "1234567890123456",
+            "++1 555 123 4567",
+            "555-123-4567 x",
+            "555-123-4567 x1234567",
+            "555-123-4567 ext. 12a",
+            "555/123/4567",
+        ] {
+            assert_eq!(
+                Contact::validate_phone(phone).as_deref(),
+                Some("Phone Not Valid"),
+                "{}",
+                phone,
+            );
+        }
+    }
+
+    #[test]
+    fn normalized_phone_of_us_formats() {
+        for phone in [
+            "555-123-4567",
+            "(555) 123-4567",
+            "555.123.4567",
+            "555 123 4567",
+            "5551234567",
+            "1-555-123-4567",
+            "1 (555) 123-4567",
+            "+1 555 123 4567",
+        ] {
+            assert_eq!(
+                with_phone(phone).normalized_phone(),
+                "+15551234567",
+                "{}",
+                phone
+            );
+        }
+    }
+
+    #[test]
+    fn normalized_phone_leaves_normalized_ones_alone() {
+        for phone in ["+15551234567", "+442071838750", ""] {
+            assert_eq!(with_phone(phone).normalized_phone(), phone);
+        }
+    }
+
+    #[test]
+    fn normalized_phone_leaves_what_it_cannot_tell_alone() {
+        for phone in ["123-4567", "555-123-4567 x89", "call me"] {
+            assert_eq!(with_phone(phone).normalized_phone(), phone);
+        }
+    }
+
+    #[test]
+    fn formatted_phone_groups_north_american_numbers() {
+        for phone in [
+            "5551234567",
+            "555-123-4567",
+            "(555) 123-4567",
+            "+15551234567",
+        ] {
+            let expected = if phone.starts_with('+') {
+                "+1 (555) 123-4567"
+            } else {
+                "(555) 123-4567"
+            };
+            assert_eq!(
+                with_phone(phone).formatted_phone(PhoneLocale::NorthAmerica),
+                expected,
+                "{}",
+                phone
+            );
+        }
+    }
+
+    #[test]
+    fn formatted_phone_leaves_short_numbers_alone() {
+        for phone in ["123-4567", "911", ""] {
+            assert_eq!(
+                with_phone(phone).formatted_phone(PhoneLocale::NorthAmerica),
+                phone
+            );
+        }
+    }
+
+    #[test]
+    fn formatted_phone_in_international_locale_is_as_entered() {
+        for phone in ["5551234567", "+15551234567", "+44 20 7183 8750"] {
+            assert_eq!(
+                with_phone(phone).formatted_phone(PhoneLocale::International),
+                phone
+            );
+        }
+    }
+
+    fn with_name(first: &str, last: &str) -> Contact {
+        Contact::builder()
+            .first(first.to_string())
+            .last(last.to_string())
+            .phone("".to_string())
+            .email("ada@example.com".to_string())
+            .build()
+    }
+
+    #[test]
+    fn suggest_name_split_of_a_full_name_in_one_field() {
+        assert_eq!(
+            with_name("Ada Lovelace", "").suggest_name_split(),
+            Some(("Ada".to_string(), "Lovelace".to_string())),
+        );
+        assert_eq!(
+            with_name("", " Grace  Brewster Hopper ").suggest_name_split(),
+            Some(("Grace  Brewster".to_string(), "Hopper".to_string())),
+        );
+    }
+
+    #[test]
+    fn suggest_name_split_leaves_single_words_alone() {
+        assert_eq!(with_name("Ada", "").suggest_name_split(), None);
+        assert_eq!(with_name("", "Lovelace").suggest_name_split(), None);
+        assert_eq!(with_name("", "").suggest_name_split(), None);
+    }
+
+    #[test]
+    fn suggest_name_split_leaves_split_names_alone() {
+        assert_eq!(with_name("Ada", "Lovelace").suggest_name_split(), None);
+        assert_eq!(with_name("Ada King", "Lovelace").suggest_name_split(), None);
+    }
+
+    #[test]
+    fn last_name_initial_ignores_accents_and_case() {
+        for (last, initial) in [
+            ("Lovelace", 'L'),
+            ("de Morgan", 'D'),
+            ("Álvarez", 'A'),
+            ("Ødegaard", OTHER_INITIAL),
+            ("42", OTHER_INITIAL),
+            ("", OTHER_INITIAL),
+        ] {
+            let contact = Contact::builder()
+                .first("".to_string())
+                .last(last.to_string())
+                .phone("".to_string())
+                .email("".to_string())
+                .build();
+            assert_eq!(contact.last_name_initial(), initial, "{}", last);
+        }
+    }
+
+    #[test]
+    fn to_vcard_escapes_text_values() {
+        let contact = Contact::builder()
+            .first("Ada, Countess".to_string())
+            .last("King;\\Lovelace\r\nByron".to_string())
+            .phone("".to_string())
+            .email("ada@example.com".to_string())
+            .build();
+        assert_eq!(
+            contact.to_vcard(),
+            concat!(
+                "BEGIN:VCARD\r\n",
+                "VERSION:4.0\r\n",
+                r"N:King\;\\Lovelace\nByron;Ada\, Countess;;;",
+                "\r\n",
+                r"FN:Ada\, Countess King\;\\Lovelace\nByron",
+                "\r\n",
+                "EMAIL:ada@example.com\r\n",
+                "END:VCARD\r\n",
+            ),
+        );
+    }
+
+    #[test]
+    fn masked_phone_shows_the_last_four_digits() {
+        for (phone, masked) in [
+            ("5551234567", "(***) ***-4567"),
+            ("+15551234567", "+* (***) ***-4567"),
+            ("+44 20 7183 8750", "+** ** **** 8750"),
+            ("123", "123"),
+            ("", ""),
+        ] {
+            assert_eq!(
+                with_phone(phone).masked_phone(PhoneLocale::NorthAmerica),
+                masked,
+                "{}",
+                phone
+            );
+        }
+    }
+
+    #[test]
+    fn masked_phone_leaves_the_extension_alone() {
+        for (phone, masked) in [
+            ("555-123-4567 x89", "***-***-4567 x89"),
+            ("555-123-4567x1234", "***-***-4567x1234"),
+            ("+44 20 7183 8750 ext. 12", "+** ** **** 8750 ext. 12"),
+        ] {
+            assert_eq!(
+                with_phone(phone).masked_phone(PhoneLocale::NorthAmerica),
+                masked,
+                "{}",
+                phone
+            );
+        }
+    }
+
+    #[test]
+    fn masked_email_shows_the_first_character_and_the_domain() {
+        for (email, masked) in [
+            ("ada@example.com", "a***@example.com"),
+            ("élodie@example.com", "é***@example.com"),
+            ("@example.com", "***@example.com"),
+            ("not an email", "***"),
+        ] {
+            let contact = Contact::builder()
+                .first("Ada".to_string())
+                .last("Lovelace".to_string())
+                .phone("".to_string())
+                .email(email.to_string())
+                .build();
+            assert_eq!(contact.masked_email(), masked, "{}", email);
+        }
+    }
+
+    #[test]
+    fn similarity_tolerates_typos() {
+        let john = with_name("John", "Backus");
+        assert!(john.similarity("Jhon") >= 0.7);
+        assert!(john.similarity("backsu") >= 0.7);
+        assert!(john.similarity("john") == 1.0);
+        // Too short for a typo to be told from another name.
+        assert_eq!(typo_similarity("Ada", "Adu"), 0.0);
+        assert_eq!(typo_similarity("Ada", "ada"), 1.0);
+        assert!(john.similarity("Jane") < 0.2);
+    }
+
+    #[test]
+    fn typo_count_counts_swaps_as_one() {
+        let count = |a: &str, b: &str| {
+            typo_count(
+                &a.chars().collect::<Vec<_>>(),
+                &b.chars().collect::<Vec<_>>(),
+            )
+        };
+        assert_eq!(count("john", "john"), 0);
+        assert_eq!(count("john", "jhon"), 1);
+        assert_eq!(count("john", "jon"), 1);
+        assert_eq!(count("john", "johnn"), 1);
+        assert_eq!(count("john", "joan"), 1);
+        assert_eq!(count("", "abc"), 3);
+        assert_eq!(count("ca", "abc"), 3);
+    }
+
+    #[test]
+    fn gravatar_url_hashes_the_trimmed_lowercase_email() {
+        let contact = Contact::builder()
+            .first("".to_string())
+            .last("".to_string())
+            .phone("".to_string())
+            .email(" MyEmailAddress@example.com ".to_string())
+            .build();
+        assert_eq!(
+            contact.gravatar_url(80),
+            "https://www.gravatar.com/avatar/0bc83cb571cd1c50ba6f3e8a78ef1346?s=80&d=identicon",
+        );
+    }
+
+    #[test]
+    fn phone_digits_for_search_of_phone_like_queries() {
+        assert_eq!(
+            phone_digits_for_search("123-4567").as_deref(),
+            Some("1234567")
+        );
+        assert_eq!(
+            phone_digits_for_search(" (555) 123 ").as_deref(),
+            Some("555123"),
+        );
+        assert_eq!(phone_digits_for_search("ada"), None);
+        assert_eq!(phone_digits_for_search("ada1"), None);
+        assert_eq!(phone_digits_for_search("--"), None);
+    }
+}
@@ -1,147 +1,652 @@
-use std::{error::Error, sync::atomic::AtomicU32};
+use std::{
+    collections::{BTreeSet, HashMap},
+    error::Error,
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use sqlx::sqlite::SqlitePool;
+use sqlx::{
+    query::QueryAs,
+    sqlite::{
+        SqliteArguments, SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteQueryResult,
+        SqliteRow, SqliteStatement, SqliteTypeInfo,
+    },
+    ConnectOptions, Describe, Either, Execute, Executor, FromRow, QueryBuilder, Row, Sqlite,
+};
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
 
-use crate::contact_model::{Contact, ContactErrors, ContactId};
+use crate::contact_model::{
+    fold_for_search, phone_digits_for_search, Contact, ContactErrors, ContactId,
+};
 
 const ERR_EMAIL_UNIQUE: &str = "Email Must Be Unique";
 
-/// TODO: move to somewhere more properly.
-pub const PAGE_SIZE: u32 = 10;
+/// The most contacts a page can hold.
+pub const MAX_PAGE_SIZE: u32 = 100;
+/// How many contacts [`ContactRepo::all_matching`] fetches ahead of the
+/// consumer.
+const STREAM_BUFFER_SIZE: usize = 16;
+/// How similar a contact has to be to be found by [`ContactRepo::fuzzy_search`].
+pub const FUZZY_SEARCH_THRESHOLD: f32 = 0.2;
+/// The most contacts [`ContactRepo::fuzzy_search`] finds, the most similar
+/// ones.
+const FUZZY_SEARCH_MAX_MATCHES: usize = 100;
+
+/// The condition of the contacts [`ContactRepo::search`] matches. Its
+/// parameters are bound by [`bind_matching`], and any that come after them
+/// start from `?4`.
+const MATCHING: &str = r#"
+    deleted_at IS NULL AND (
+        ?1 IS NULL OR
+        first_folded LIKE ("%" || ?1 || "%") OR
+        last_folded LIKE ("%" || ?1 || "%") OR
+        phone LIKE ("%" || ?1 || "%") OR
+        ?2 IS NOT NULL AND replace(replace(replace(replace(replace(replace(
+            phone, ' ', ''), '-', ''), '.', ''), '(', ''), ')', ''), '+', ''
+        ) LIKE ("%" || ?2 || "%") OR
+        email_folded LIKE ("%" || ?1 || "%")
+    ) AND (
+        ?3 IS NULL OR
+        CASE
+            WHEN upper(substr(last_folded, 1, 1)) BETWEEN 'A' AND 'Z'
+            THEN upper(substr(last_folded, 1, 1))
+            ELSE '#'
+        END = ?3
+    )"#;
+
+const CREATE_CONTACT_TABLE: &str = "
+    CREATE TABLE IF NOT EXISTS contact (
+        id          INTEGER PRIMARY KEY AUTOINCREMENT,
+        first       TEXT,
+        last        TEXT,
+        phone       TEXT,
+        email       TEXT NOT NULL,
+        deleted_at  TEXT,
+        -- The columns above as `fold_for_search` leaves them, for searching.
+        first_folded    TEXT,
+        last_folded     TEXT,
+        email_folded    TEXT
+    )
+";
+
+/// Counts the changes made to the `contact` table, in a table of a single
+/// row, so that what was read before a change can be told apart from what is
+/// there after it. See [`ContactRepo::revision`].
+const CREATE_CONTACT_REVISION: [&str; 5] = [
+    "CREATE TABLE IF NOT EXISTS contact_revision (revision INTEGER NOT NULL)",
+    "
+    INSERT INTO contact_revision (revision)
+    SELECT 0 WHERE NOT EXISTS (SELECT * FROM contact_revision)
+    ",
+    "
+    CREATE TRIGGER IF NOT EXISTS contact_inserted AFTER INSERT ON contact
+    BEGIN UPDATE contact_revision SET revision = revision + 1; END
+    ",
+    "
+    CREATE TRIGGER IF NOT EXISTS contact_updated AFTER UPDATE ON contact
+    BEGIN UPDATE contact_revision SET revision = revision + 1; END
+    ",
+    "
+    CREATE TRIGGER IF NOT EXISTS contact_deleted AFTER DELETE ON contact
+    BEGIN UPDATE contact_revision SET revision = revision + 1; END
+    ",
+];
+
+pub struct ContactsPage {
+    pub contacts: Vec<Contact>,
+    pub has_previous: bool,
+    pub has_next: bool,
+}
+
+/// A contact deleted by [`ContactRepo::delete`] or
+/// [`ContactRepo::delete_many`], and not yet purged.
+pub struct DeletedContact {
+    pub contact: Contact,
+    /// How long ago it was deleted.
+    pub deleted_ago: Duration,
+}
+
+pub struct DeletedContactsPage {
+    pub contacts: Vec<DeletedContact>,
+    pub has_previous: bool,
+    pub has_next: bool,
+}
+
+/// Why [`ContactRepo::validate_email`] turned an email down.
+pub enum EmailError {
+    /// See [`Contact::validate_email`].
+    Invalid(String),
+    /// The contact of the id has the email already.
+    Taken(ContactId),
+}
+impl EmailError {
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Invalid(message) => message,
+            Self::Taken(_) => ERR_EMAIL_UNIQUE,
+        }
+    }
+}
 
 pub struct ContactRepo {
     pool: SqlitePool,
-
-    next_id: AtomicU32,
+    /// How many queries have been made, if they are being counted. See
+    /// [`Self::counting_queries`].
+    query_count: Option<Arc<AtomicUsize>>,
 }
 impl ContactRepo {
+    /// Opens the database at `url`, creating the `contact` table if it is not
+    /// there yet.
+    ///
+    /// Statements are logged at the debug level, as there are a few of them
+    /// for every request.
+    pub async fn connect(url: &str) -> Result<Self, Box<dyn Error>> {
+        let options = SqliteConnectOptions::from_str(url)?.log_statements(log::LevelFilter::Debug);
+        let pool = SqlitePoolOptions::new()
+            .max_lifetime(None)
+            .idle_timeout(None)
+            .connect_with(options)
+            .await?;
+
+        Self::build(pool).await
+    }
+
+    /// Has the repo count the queries it makes from now on, so that tests can
+    /// tell how many a request takes. See [`Self::query_count`].
+    pub fn counting_queries(self) -> Self {
+        Self {
+            query_count: Some(Arc::new(AtomicUsize::new(0))),
+            ..self
+        }
+    }
+
+    /// How many queries have been made since [`Self::counting_queries`], or
+    /// `None` without it. Starting and committing transactions don't count.
+    pub fn query_count(&self) -> Option<usize> {
+        self.query_count
+            .as_ref()
+            .map(|count| count.load(Ordering::Relaxed))
+    }
+
+    /// Makes a trivial round trip to the database, to check that it can be
+    /// reached.
+    pub async fn ping(&self) -> Result<(), Box<dyn Error>> {
+        sqlx::query("SELECT 1")
+            .execute(self.counted(&self.pool))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Closes the connections to the database, waiting for the ones in use to
+    /// be given back.
+    pub async fn close(&self) {
+        self.pool.close().await;
+    }
+
+    /// Creates the `contact` table if it is not there yet, migrating one from
+    /// before soft deletion or folded columns.
+    ///
+    /// Emails are only unique among contacts that are not deleted, so that a
+    /// deleted contact doesn't hold on to its email.
     pub async fn build(pool: SqlitePool) -> Result<Self, Box<dyn Error>> {
+        sqlx::query(CREATE_CONTACT_TABLE).execute(&pool).await?;
+
+        let (has_deleted_at,): (bool,) = sqlx::query_as(
+            "SELECT count(*) > 0 FROM pragma_table_info('contact') WHERE name = 'deleted_at'",
+        )
+        .fetch_one(&pool)
+        .await?;
+        if !has_deleted_at {
+            // The old table has a `UNIQUE` constraint on `email`, which SQLite
+            // can only drop by rebuilding the table.
+            let mut tx = pool.begin().await?;
+            sqlx::query("ALTER TABLE contact RENAME TO contact_before_soft_delete")
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query(CREATE_CONTACT_TABLE).execute(&mut *tx).await?;
+            sqlx::query(
+                "
+                INSERT INTO contact (id, first, last, phone, email)
+                SELECT id, first, last, phone, email FROM contact_before_soft_delete
+            ",
+            )
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query("DROP TABLE contact_before_soft_delete")
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+        }
+
+        let (has_folded,): (bool,) = sqlx::query_as(
+            "SELECT count(*) > 0 FROM pragma_table_info('contact') WHERE name = 'first_folded'",
+        )
+        .fetch_one(&pool)
+        .await?;
+        if !has_folded {
+            for column in ["first_folded", "last_folded", "email_folded"] {
+                sqlx::query(&format!("ALTER TABLE contact ADD COLUMN {} TEXT", column))
+                    .execute(&pool)
+                    .await?;
+            }
+        }
+        Self::fill_folded_columns(&pool).await?;
+
         sqlx::query(
             "
-            CREATE TABLE contact (
-                id      INTEGER PRIMARY KEY,
-                first   TEXT,
-                last    TEXT,
-                phone   TEXT,
-                email   TEXT UNIQUE NOT NULL
-            )
+            CREATE UNIQUE INDEX IF NOT EXISTS contact_email_unique
+            ON contact (email) WHERE deleted_at IS NULL
         ",
         )
         .execute(&pool)
         .await?;
 
+        for statement in CREATE_CONTACT_REVISION {
+            sqlx::query(statement).execute(&pool).await?;
+        }
+
         Ok(Self {
             pool,
-
-            next_id: AtomicU32::new(0),
+            query_count: None,
         })
     }
-    pub async fn build_with_fake_data(pool: SqlitePool, n: u32) -> Result<Self, Box<dyn Error>> {
-        let c = Self::build(pool).await?;
 
-        {
-            let mut tx = c.pool.begin().await?;
-
-            for id in 0..n {
-                let contact = Contact::new_fake(ContactId::new(id));
-                Self::execute_save(&mut *tx, &contact).await?;
-            }
-            c.next_id.store(n, std::sync::atomic::Ordering::Relaxed);
+    /// Fills in the folded columns of the contacts saved before there were
+    /// any, as SQLite can't fold text by itself.
+    async fn fill_folded_columns(pool: &SqlitePool) -> Result<(), Box<dyn Error>> {
+        let rows: Vec<(u32, String, String, String)> = sqlx::query_as(
+            "
+            SELECT id, coalesce(first, ''), coalesce(last, ''), email
+            FROM contact WHERE first_folded IS NULL
+        ",
+        )
+        .fetch_all(pool)
+        .await?;
+        if rows.is_empty() {
+            return Ok(());
+        }
 
-            tx.commit().await?;
+        let mut tx = pool.begin().await?;
+        for (id, first, last, email) in rows {
+            sqlx::query(
+                "UPDATE contact SET first_folded = ?, last_folded = ?, email_folded = ? WHERE id = ?",
+            )
+            .bind(fold_for_search(&first))
+            .bind(fold_for_search(&last))
+            .bind(fold_for_search(&email))
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
         }
+        tx.commit().await?;
 
-        Ok(c)
+        Ok(())
     }
 
-    pub fn pop_id(&self) -> ContactId {
-        let id = self
-            .next_id
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-        ContactId::new(id)
+    /// Fills the repo with `n` fake contacts, unless it already has contacts.
+    /// Returns whether it did so.
+    pub async fn seed_fake_data(&self, n: u32) -> Result<bool, Box<dyn Error>> {
+        if n == 0 || self.count().await? > 0 {
+            return Ok(false);
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        for _ in 0..n {
+            let contact = Contact::new_fake();
+            Self::execute_save(self.counted(&mut *tx), &contact).await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(true)
     }
 
     pub async fn count(&self) -> Result<u32, Box<dyn Error>> {
-        let (count,): (u32,) = sqlx::query_as("SELECT count(*) FROM contact")
-            .fetch_one(&self.pool)
-            .await?;
+        let (count,): (u32,) =
+            sqlx::query_as("SELECT count(*) FROM contact WHERE deleted_at IS NULL")
+                .fetch_one(self.counted(&self.pool))
+                .await?;
 
         Ok(count)
     }
 
-    pub async fn all(&self) -> Result<Vec<Contact>, Box<dyn Error>> {
-        let contacts: Vec<Contact> = sqlx::query_as("SELECT * FROM contact")
-            .fetch_all(&self.pool)
+    /// A number that changes whenever a contact is created, changed, deleted,
+    /// restored or purged.
+    pub async fn revision(&self) -> Result<u64, Box<dyn Error>> {
+        let (revision,): (i64,) = sqlx::query_as("SELECT revision FROM contact_revision")
+            .fetch_one(self.counted(&self.pool))
             .await?;
-        Ok(contacts)
+
+        Ok(revision as u64)
     }
 
-    pub async fn all_by_page(&self, page: u32) -> Result<Vec<Contact>, Box<dyn Error>> {
-        let page = page.max(1);
+    /// Counts the contacts [`Self::search`] would go through for `q` and
+    /// `initial`.
+    pub async fn count_matching(
+        &self,
+        q: Option<&str>,
+        initial: Option<char>,
+    ) -> Result<u32, Box<dyn Error>> {
+        if q.is_none() && initial.is_none() {
+            return self.count().await;
+        }
+
+        let sql = format!("SELECT count(*) FROM contact WHERE {}", MATCHING);
+        let (count,): (u32,) = bind_matching(sqlx::query_as(&sql), q, initial)
+            .fetch_one(self.counted(&self.pool))
+            .await?;
 
-        let contacts: Vec<Contact> = sqlx::query_as(
-            r#"SELECT * FROM contact
-            LIMIT ? OFFSET ?"#,
+        Ok(count)
+    }
+
+    /// Counts the contacts by the initial of their last names, accents
+    /// ignored as by [`Contact::last_name_initial`], in alphabetical order.
+    /// Last names not starting with a latin letter are counted under
+    /// [`crate::contact_model::OTHER_INITIAL`], which comes last.
+    pub async fn last_name_initial_counts(&self) -> Result<Vec<(char, u32)>, Box<dyn Error>> {
+        let counts: Vec<(String, u32)> = sqlx::query_as(
+            r#"
+            SELECT
+                CASE
+                    WHEN upper(substr(last_folded, 1, 1)) BETWEEN 'A' AND 'Z'
+                    THEN upper(substr(last_folded, 1, 1))
+                    ELSE '#'
+                END AS initial,
+                count(*)
+            FROM contact
+            WHERE deleted_at IS NULL
+            GROUP BY initial
+            ORDER BY initial = '#', initial"#,
         )
-        .bind(PAGE_SIZE)
-        .bind((page - 1) * PAGE_SIZE)
-        .fetch_all(&self.pool)
+        .fetch_all(self.counted(&self.pool))
         .await?;
+
+        Ok(counts
+            .into_iter()
+            .filter_map(|(initial, count)| Some((initial.chars().next()?, count)))
+            .collect())
+    }
+
+    pub async fn all(&self) -> Result<Vec<Contact>, Box<dyn Error>> {
+        let contacts: Vec<Contact> =
+            sqlx::query_as("SELECT * FROM contact WHERE deleted_at IS NULL")
+                .fetch_all(self.counted(&self.pool))
+                .await?;
         Ok(contacts)
     }
 
-    pub async fn search(&self, q: &str, page: u32) -> Result<Vec<Contact>, Box<dyn Error>> {
+    /// Streams the contacts [`Self::search`] would go through for `q`, without
+    /// holding them all in memory.
+    pub fn all_matching(&self, q: Option<String>) -> ReceiverStream<Result<Contact, sqlx::Error>> {
+        let pool = self.pool.clone();
+        let query_count = self.query_count.clone();
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER_SIZE);
+
+        tokio::spawn(async move {
+            let executor = Counted {
+                inner: &pool,
+                count: query_count.as_deref(),
+            };
+            let sql = format!("SELECT * FROM contact WHERE {} ORDER BY id", MATCHING);
+            let mut rows = bind_matching(sqlx::query_as(&sql), q.as_deref(), None).fetch(executor);
+            while let Some(row) = rows.next().await {
+                if tx.send(row).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        ReceiverStream::new(rx)
+    }
+
+    /// Fetches the `page`th page, of `page_size` contacts, of the contacts
+    /// [`Self::search`] would go through for `q` and `initial`, in the order
+    /// of their ids, so that no contact shows up on two pages or none.
+    ///
+    /// Whether there is a next page is found out by fetching one more contact
+    /// than a page holds, which is cheaper than counting them all.
+    pub async fn page_with_neighbors(
+        &self,
+        page: u32,
+        page_size: u32,
+        q: Option<&str>,
+        initial: Option<char>,
+    ) -> Result<ContactsPage, Box<dyn Error>> {
         let page = page.max(1);
+        let page_size = page_size.clamp(1, MAX_PAGE_SIZE);
 
-        let contacts: Vec<Contact> = sqlx::query_as(
-            r#"
-            SELECT * FROM contact 
-            WHERE
-                first LIKE ("%" || ? || "%") OR
-                last LIKE ("%" || ? || "%")
-                LIMIT ? OFFSET ?"#,
-        )
-        .bind(q)
-        .bind(q)
-        .bind(PAGE_SIZE)
-        .bind((page - 1) * PAGE_SIZE)
-        .fetch_all(&self.pool)
-        .await?;
+        let mut contacts = self
+            .search(q, initial, (page - 1) * page_size, page_size + 1)
+            .await?;
+        let has_next = contacts.len() > page_size as usize;
+        contacts.truncate(page_size as usize);
+
+        Ok(ContactsPage {
+            contacts,
+            has_previous: page > 1,
+            has_next,
+        })
+    }
+
+    /// Matches `q` against the names, phone and email of the contacts,
+    /// ignoring case and accents by [`fold_for_search`], so "GMAIL" finds
+    /// "gmail.com" and "jose" finds "José". Phone numbers are matched by their
+    /// digits alone, by [`phone_digits_for_search`]. With `initial`, only the
+    /// contacts whose [`Contact::last_name_initial`] it is are matched.
+    /// Without either, all contacts are. Deleted contacts never are.
+    async fn search(
+        &self,
+        q: Option<&str>,
+        initial: Option<char>,
+        offset: u32,
+        limit: u32,
+    ) -> Result<Vec<Contact>, Box<dyn Error>> {
+        let sql = format!(
+            "SELECT * FROM contact WHERE {} ORDER BY id LIMIT ?4 OFFSET ?5",
+            MATCHING
+        );
+        let contacts: Vec<Contact> = bind_matching(sqlx::query_as(&sql), q, initial)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(self.counted(&self.pool))
+            .await?;
         Ok(contacts)
     }
 
+    /// Finds the contacts whose names are at least `threshold` similar to `q`
+    /// by [`Contact::similarity`], the most similar first. With `initial`,
+    /// only the contacts whose [`Contact::last_name_initial`] it is are found.
+    ///
+    /// Unlike [`Self::search`], this tolerates typos, but there is no index
+    /// for it, so every contact is streamed through and scored. Only the best
+    /// [`FUZZY_SEARCH_MAX_MATCHES`] are kept, so that however many contacts
+    /// there are, at most twice as many are held at a time.
+    pub async fn fuzzy_search(
+        &self,
+        q: &str,
+        initial: Option<char>,
+        threshold: f32,
+    ) -> Result<Vec<Contact>, Box<dyn Error>> {
+        fn keep_best(scored: &mut Vec<(f32, Contact)>) {
+            scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+            scored.truncate(FUZZY_SEARCH_MAX_MATCHES);
+        }
+
+        let mut scored: Vec<(f32, Contact)> = Vec::new();
+        let mut contacts = self.all_matching(None);
+        while let Some(contact) = contacts.next().await {
+            let contact = contact?;
+            if initial.is_some_and(|initial| contact.last_name_initial() != initial) {
+                continue;
+            }
+            let similarity = contact.similarity(q);
+            if similarity >= threshold {
+                scored.push((similarity, contact));
+                if scored.len() >= 2 * FUZZY_SEARCH_MAX_MATCHES {
+                    keep_best(&mut scored);
+                }
+            }
+        }
+        keep_best(&mut scored);
+
+        Ok(scored.into_iter().map(|(_, contact)| contact).collect())
+    }
+
+    /// Finds pairs of contacts that are likely the same person: those whose
+    /// emails only differ in case or surrounding spaces, and those with the
+    /// same name. Each pair comes once, in the order of the ids.
+    pub async fn find_potential_duplicates(
+        &self,
+    ) -> Result<Vec<(Contact, Contact)>, Box<dyn Error>> {
+        let mut contacts = self.all().await?;
+        contacts.sort_by_key(|contact| contact.id().map(|id| id.value()));
+
+        let mut groups: HashMap<(bool, String), Vec<usize>> = HashMap::new();
+        for (i, contact) in contacts.iter().enumerate() {
+            let email = contact.email().trim().to_lowercase();
+            groups.entry((false, email)).or_default().push(i);
+
+            let name = format!("{} {}", contact.first().trim(), contact.last().trim());
+            if !name.trim().is_empty() {
+                groups
+                    .entry((true, name.to_lowercase()))
+                    .or_default()
+                    .push(i);
+            }
+        }
+
+        // Groups list the contacts in the order of the ids, and a pair that
+        // shares both its email and its name is only kept once.
+        let pairs: BTreeSet<(usize, usize)> = groups
+            .values()
+            .flat_map(|group| {
+                group
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(n, &a)| group[n + 1..].iter().map(move |&b| (a, b)))
+            })
+            .collect();
+
+        Ok(pairs
+            .into_iter()
+            .map(|(a, b)| (contacts[a].clone(), contacts[b].clone()))
+            .collect())
+    }
+
+    /// Merges the contact of `remove_id` into the one of `keep_id`, as
+    /// [`Contact::merge_from`] does, then deletes it, in a single transaction.
+    /// Returns the labels of the fields where the kept contact's value won
+    /// over a different one, or `None` if either contact is not there.
+    pub async fn merge(
+        &self,
+        keep_id: ContactId,
+        remove_id: ContactId,
+    ) -> Result<Option<Vec<&'static str>>, Box<dyn Error>> {
+        if keep_id == remove_id {
+            return Ok(None);
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        let mut contacts: Vec<Contact> =
+            sqlx::query_as("SELECT * FROM contact WHERE id IN (?1, ?2) AND deleted_at IS NULL")
+                .bind(keep_id.value())
+                .bind(remove_id.value())
+                .fetch_all(self.counted(&mut *tx))
+                .await?;
+        if contacts.len() < 2 {
+            return Ok(None);
+        }
+        let keep_index = usize::from(contacts[0].id() != Some(keep_id));
+        let mut keep = contacts.swap_remove(keep_index);
+        let remove = contacts.pop().unwrap();
+
+        let conflicts = keep.merge_from(&remove);
+        Self::execute_delete(self.counted(&mut *tx), remove_id).await?;
+        // The email of the kept contact is never replaced, so it stays unique.
+        Self::execute_update(self.counted(&mut *tx), &keep).await?;
+
+        tx.commit().await?;
+
+        Ok(Some(conflicts))
+    }
+
+    /// Saves `contact` as a new contact, ignoring its id, and returns the id
+    /// SQLite assigned to it.
     pub async fn save(
         &self,
         contact: &Contact,
-    ) -> Result<Result<(), ContactErrors>, Box<dyn Error>> {
+    ) -> Result<Result<ContactId, ContactErrors>, Box<dyn Error>> {
         if let Err(errors) = contact.validate() {
             return Ok(Err(errors));
         }
 
-        if !Self::execute_save(&self.pool, contact).await? {
+        let Some(id) = Self::execute_save(self.counted(&self.pool), contact).await? else {
             return Ok(Err(ContactErrors {
                 email: Some(ERR_EMAIL_UNIQUE.to_string()),
                 ..Default::default()
             }));
         };
 
-        Ok(Ok(()))
+        Ok(Ok(id))
+    }
+
+    /// Saves `contacts` as new contacts in a single transaction. Contacts that
+    /// can't be saved are reported in place and don't stop the others.
+    pub async fn save_many(
+        &self,
+        contacts: &[Contact],
+    ) -> Result<Vec<Result<ContactId, ContactErrors>>, Box<dyn Error>> {
+        let mut results = Vec::with_capacity(contacts.len());
+        let mut tx = self.pool.begin().await?;
+
+        for contact in contacts {
+            if let Err(errors) = contact.validate() {
+                results.push(Err(errors));
+                continue;
+            }
+
+            results.push(
+                match Self::execute_save(self.counted(&mut *tx), contact).await? {
+                    Some(id) => Ok(id),
+                    None => Err(ContactErrors {
+                        email: Some(ERR_EMAIL_UNIQUE.to_string()),
+                        ..Default::default()
+                    }),
+                },
+            );
+        }
+
+        tx.commit().await?;
+
+        Ok(results)
     }
 
     pub async fn find(&self, id: ContactId) -> Result<Option<Contact>, Box<dyn Error>> {
-        let contact: Option<Contact> = sqlx::query_as("SELECT * FROM contact WHERE id = ?")
-            .bind(id.value())
-            .fetch_optional(&self.pool)
-            .await?;
+        let contact: Option<Contact> =
+            sqlx::query_as("SELECT * FROM contact WHERE id = ? AND deleted_at IS NULL")
+                .bind(id.value())
+                .fetch_optional(self.counted(&self.pool))
+                .await?;
 
         Ok(contact)
     }
 
     pub async fn find_by_email(&self, id: String) -> Result<Option<Contact>, Box<dyn Error>> {
-        let contact: Option<Contact> = sqlx::query_as("SELECT * FROM contact WHERE email = ?")
-            .bind(id)
-            .fetch_optional(&self.pool)
-            .await?;
+        let contact: Option<Contact> =
+            sqlx::query_as("SELECT * FROM contact WHERE email = ? AND deleted_at IS NULL")
+                .bind(id)
+                .fetch_optional(self.counted(&self.pool))
+                .await?;
 
         Ok(contact)
     }
@@ -154,59 +659,211 @@ impl ContactRepo {
             return Ok(Err(errors));
         }
 
-        Self::execute_update(&self.pool, contact).await?;
+        if !Self::execute_update(self.counted(&self.pool), contact).await? {
+            return Ok(Err(ContactErrors {
+                email: Some(ERR_EMAIL_UNIQUE.to_string()),
+                ..Default::default()
+            }));
+        }
 
         Ok(Ok(()))
     }
 
-    pub async fn delete(&self, contact_id: ContactId) -> Result<(), Box<dyn Error>> {
-        Self::execute_delete(&self.pool, contact_id).await?;
+    /// Fetches the `page`th page, of `page_size` contacts, of the deleted
+    /// contacts, the most recently deleted first.
+    pub async fn all_deleted_by_page(
+        &self,
+        page: u32,
+        page_size: u32,
+    ) -> Result<DeletedContactsPage, Box<dyn Error>> {
+        let page = page.max(1);
+        let page_size = page_size.clamp(1, MAX_PAGE_SIZE);
+
+        let rows = sqlx::query(
+            "
+            SELECT
+                *,
+                max(unixepoch('now') - unixepoch(deleted_at), 0) AS deleted_seconds_ago
+            FROM contact
+            WHERE deleted_at IS NOT NULL
+            ORDER BY deleted_at DESC, id DESC
+            LIMIT ?1 OFFSET ?2
+        ",
+        )
+        .bind(page_size + 1)
+        .bind((page - 1) * page_size)
+        .fetch_all(self.counted(&self.pool))
+        .await?;
+        let mut contacts = rows
+            .iter()
+            .map(|row| {
+                Ok(DeletedContact {
+                    contact: Contact::from_row(row)?,
+                    deleted_ago: Duration::from_secs(
+                        row.try_get::<i64, _>("deleted_seconds_ago")? as u64
+                    ),
+                })
+            })
+            .collect::<Result<Vec<_>, sqlx::Error>>()?;
+        let has_next = contacts.len() > page_size as usize;
+        contacts.truncate(page_size as usize);
 
-        Ok(())
+        Ok(DeletedContactsPage {
+            contacts,
+            has_previous: page > 1,
+            has_next,
+        })
+    }
+
+    /// Removes the deleted contacts for good, in a single transaction. Returns
+    /// how many there were.
+    pub async fn purge_deleted(&self) -> Result<u64, Box<dyn Error>> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query("DELETE FROM contact WHERE deleted_at IS NOT NULL")
+            .execute(self.counted(&mut *tx))
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Marks the contact as deleted, which hides it everywhere until it is
+    /// [restored](Self::restore). Returns whether there was such a contact.
+    pub async fn delete(&self, contact_id: ContactId) -> Result<bool, Box<dyn Error>> {
+        Ok(Self::execute_delete(self.counted(&self.pool), contact_id).await? > 0)
+    }
+
+    /// Brings back a contact deleted by [`Self::delete`] or
+    /// [`Self::delete_many`]. Returns whether there was such a contact, or
+    /// errors if another contact has taken its email in the meantime.
+    pub async fn restore(
+        &self,
+        contact_id: ContactId,
+    ) -> Result<Result<bool, ContactErrors>, Box<dyn Error>> {
+        Ok(
+            match Self::execute_restore(self.counted(&self.pool), contact_id).await? {
+                Some(restored) => Ok(restored),
+                None => Err(ContactErrors {
+                    email: Some(ERR_EMAIL_UNIQUE.to_string()),
+                    ..Default::default()
+                }),
+            },
+        )
     }
 
+    /// Brings back all of `contact_ids` at once, in a single transaction, as
+    /// [`Self::restore`] does. Contacts whose emails have been taken in the
+    /// meantime stay deleted, and don't stop the others. Returns how many of
+    /// the contacts were restored, and how many of them were not because of
+    /// their emails.
+    pub async fn restore_many(
+        &self,
+        contact_ids: &[ContactId],
+    ) -> Result<(u64, u64), Box<dyn Error>> {
+        let (mut restored, mut conflicted) = (0, 0);
+        let mut tx = self.pool.begin().await?;
+
+        for contact_id in contact_ids {
+            match Self::execute_restore(self.counted(&mut *tx), *contact_id).await? {
+                Some(true) => restored += 1,
+                Some(false) => {}
+                None => conflicted += 1,
+            }
+        }
+
+        tx.commit().await?;
+
+        Ok((restored, conflicted))
+    }
+
+    /// Deletes all of `contact_ids` at once, in a single transaction, as
+    /// [`Self::delete`] does. Returns the ids of the contacts that were
+    /// actually there to be deleted.
+    pub async fn delete_many(
+        &self,
+        contact_ids: &[ContactId],
+    ) -> Result<Vec<ContactId>, Box<dyn Error>> {
+        if contact_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        let mut query = QueryBuilder::new(
+            "UPDATE contact SET deleted_at = datetime('now') WHERE deleted_at IS NULL AND id IN (",
+        );
+        let mut separated = query.separated(", ");
+        for contact_id in contact_ids {
+            separated.push_bind(contact_id.value());
+        }
+        separated.push_unseparated(") RETURNING id");
+        let deleted: Vec<(u32,)> = query
+            .build_query_as()
+            .fetch_all(self.counted(&mut *tx))
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(deleted
+            .into_iter()
+            .map(|(id,)| ContactId::new(id))
+            .collect())
+    }
+
+    /// Checks whether the contact of `contact_id`, or a new contact if it is
+    /// `None`, can use `email`.
     pub async fn validate_email(
         &self,
         contact_id: Option<ContactId>,
         email: String,
-    ) -> Result<Option<String>, Box<dyn Error>> {
+    ) -> Result<Option<EmailError>, Box<dyn Error>> {
         if let Some(err) = Contact::validate_email(&email) {
-            return Ok(Some(err));
+            return Ok(Some(EmailError::Invalid(err)));
         }
 
         let Some(contact_with_email) = self.find_by_email(email).await? else {
             return Ok(None);
         };
 
-        match contact_id {
-            Some(contact_id) if contact_id == contact_with_email.id() => Ok(None),
-            _ => Ok(Some(ERR_EMAIL_UNIQUE.to_string())),
+        let other_id = contact_with_email.id().unwrap();
+        if contact_id == Some(other_id) {
+            Ok(None)
+        } else {
+            Ok(Some(EmailError::Taken(other_id)))
         }
     }
 
+    /// Saves the phone number normalized, as does [`Self::execute_update`].
+    /// Returns `None` if the email is already taken.
     async fn execute_save<'a>(
         executor: impl sqlx::sqlite::SqliteExecutor<'a>,
         contact: &Contact,
-    ) -> Result<bool, Box<dyn Error>> {
+    ) -> Result<Option<ContactId>, Box<dyn Error>> {
         let result = sqlx::query(
             "
-            INSERT INTO contact (id, first, last, phone, email)
-            VALUES (?, ?, ?, ?, ?)
+            INSERT INTO contact (
+                first, last, phone, email, first_folded, last_folded, email_folded
+            )
+            VALUES (?, ?, ?, ?, ?, ?, ?)
         ",
         )
-        .bind(contact.id().value())
         .bind(contact.first())
         .bind(contact.last())
-        .bind(contact.phone())
+        .bind(contact.normalized_phone())
         .bind(contact.email())
+        .bind(fold_for_search(contact.first()))
+        .bind(fold_for_search(contact.last()))
+        .bind(fold_for_search(contact.email()))
         .execute(executor)
         .await;
         match result {
-            Ok(_) => Ok(true),
+            Ok(result) => Ok(Some(ContactId::new(result.last_insert_rowid() as u32))),
             Err(err) => 'err: {
                 if let Some(err) = err.as_database_error() {
                     if err.is_unique_violation() {
-                        break 'err Ok(false);
+                        break 'err Ok(None);
                     }
                 }
                 Err(err.into())
@@ -214,37 +871,321 @@ impl ContactRepo {
         }
     }
 
+    /// Returns `false` if the email is already taken by another contact.
     async fn execute_update<'a>(
         executor: impl sqlx::sqlite::SqliteExecutor<'a>,
         contact: &Contact,
-    ) -> Result<(), Box<dyn Error>> {
-        sqlx::query(
+    ) -> Result<bool, Box<dyn Error>> {
+        let result = sqlx::query(
             "
             UPDATE contact
-            SET first = ?, last = ?, phone = ?, email = ?
-            WHERE id = ?
+            SET
+                first = ?, last = ?, phone = ?, email = ?,
+                first_folded = ?, last_folded = ?, email_folded = ?
+            WHERE id = ? AND deleted_at IS NULL
         ",
         )
         .bind(contact.first())
         .bind(contact.last())
-        .bind(contact.phone())
+        .bind(contact.normalized_phone())
         .bind(contact.email())
-        .bind(contact.id().value())
+        .bind(fold_for_search(contact.first()))
+        .bind(fold_for_search(contact.last()))
+        .bind(fold_for_search(contact.email()))
+        .bind(contact.id().map(|id| id.value()))
         .execute(executor)
-        .await?;
+        .await;
+        match result {
+            Ok(_) => Ok(true),
+            Err(err) => 'err: {
+                if let Some(err) = err.as_database_error() {
+                    if err.is_unique_violation() {
+                        break 'err Ok(false);
+                    }
+                }
+                Err(err.into())
+            }
+        }
+    }
 
-        Ok(())
+    /// Returns whether the contact was there to be restored, or `None` if its
+    /// email is taken by another contact.
+    async fn execute_restore<'a>(
+        executor: impl sqlx::sqlite::SqliteExecutor<'a>,
+        contact_id: ContactId,
+    ) -> Result<Option<bool>, Box<dyn Error>> {
+        let result = sqlx::query(
+            "UPDATE contact SET deleted_at = NULL WHERE id = ? AND deleted_at IS NOT NULL",
+        )
+        .bind(contact_id.value())
+        .execute(executor)
+        .await;
+        match result {
+            Ok(result) => Ok(Some(result.rows_affected() > 0)),
+            Err(err) => 'err: {
+                if let Some(err) = err.as_database_error() {
+                    if err.is_unique_violation() {
+                        break 'err Ok(None);
+                    }
+                }
+                Err(err.into())
+            }
+        }
     }
 
+    /// Returns the number of deleted contacts.
     async fn execute_delete<'a>(
         executor: impl sqlx::sqlite::SqliteExecutor<'a>,
         contact_id: ContactId,
-    ) -> Result<(), Box<dyn Error>> {
-        sqlx::query("DELETE FROM contact WHERE id = ?")
-            .bind(contact_id.value())
-            .execute(executor)
-            .await?;
+    ) -> Result<u64, Box<dyn Error>> {
+        let result = sqlx::query(
+            "UPDATE contact SET deleted_at = datetime('now') WHERE id = ? AND deleted_at IS NULL",
+        )
+        .bind(contact_id.value())
+        .execute(executor)
+        .await?;
 
-        Ok(())
+        Ok(result.rows_affected())
+    }
+
+    /// Has the queries made through `executor` counted, if they are being
+    /// counted.
+    fn counted<'c, E>(&'c self, executor: E) -> Counted<'c, E> {
+        Counted {
+            inner: executor,
+            count: self.query_count.as_deref(),
+        }
+    }
+}
+
+/// Binds the parameters of [`MATCHING`]: `q` folded by [`fold_for_search`],
+/// the digits of `q` by [`phone_digits_for_search`], and `initial`.
+fn bind_matching<'q, O>(
+    query: QueryAs<'q, Sqlite, O, SqliteArguments<'q>>,
+    q: Option<&str>,
+    initial: Option<char>,
+) -> QueryAs<'q, Sqlite, O, SqliteArguments<'q>> {
+    query
+        .bind(q.map(fold_for_search))
+        .bind(q.and_then(phone_digits_for_search))
+        .bind(initial.map(String::from))
+}
+
+/// An executor that counts the queries made through it before handing them to
+/// `inner`. See [`ContactRepo::counting_queries`].
+#[derive(Debug)]
+struct Counted<'c, E> {
+    inner: E,
+    count: Option<&'c AtomicUsize>,
+}
+impl<'c, E> Counted<'c, E> {
+    fn count(&self) {
+        if let Some(count) = self.count {
+            count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+/// Every other way to run a query ends up in `fetch_many` or `fetch_optional`.
+impl<'c, E> Executor<'c> for Counted<'c, E>
+where
+    E: Executor<'c, Database = Sqlite>,
+{
+    type Database = Sqlite;
+
+    fn fetch_many<'e, 'q: 'e, Q>(
+        self,
+        query: Q,
+    ) -> Pin<Box<dyn Stream<Item = sqlx::Result<Either<SqliteQueryResult, SqliteRow>>> + Send + 'e>>
+    where
+        'c: 'e,
+        Q: Execute<'q, Sqlite> + 'q,
+    {
+        self.count();
+        self.inner.fetch_many(query)
+    }
+
+    fn fetch_optional<'e, 'q: 'e, Q>(
+        self,
+        query: Q,
+    ) -> Pin<Box<dyn Future<Output = sqlx::Result<Option<SqliteRow>>> + Send + 'e>>
+    where
+        'c: 'e,
+        Q: Execute<'q, Sqlite> + 'q,
+    {
+        self.count();
+        self.inner.fetch_optional(query)
+    }
+
+    fn prepare_with<'e, 'q: 'e>(
+        self,
+        sql: &'q str,
+        parameters: &'e [SqliteTypeInfo],
+    ) -> Pin<Box<dyn Future<Output = sqlx::Result<SqliteStatement<'q>>> + Send + 'e>>
+    where
+        'c: 'e,
+    {
+        self.inner.prepare_with(sql, parameters)
+    }
+
+    fn describe<'e, 'q: 'e>(
+        self,
+        sql: &'q str,
+    ) -> Pin<Box<dyn Future<Output = sqlx::Result<Describe<Sqlite>>> + Send + 'e>>
+    where
+        'c: 'e,
+    {
+        self.inner.describe(sql)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn repo() -> ContactRepo {
+        ContactRepo::connect("sqlite::memory:").await.unwrap()
+    }
+
+    fn contact(first: &str, last: &str, email: &str) -> Contact {
+        Contact::builder()
+            .first(first.to_string())
+            .last(last.to_string())
+            .phone("".to_string())
+            .email(email.to_string())
+            .build()
+    }
+
+    async fn save(repo: &ContactRepo, first: &str, last: &str, email: &str) -> ContactId {
+        repo.save(&contact(first, last, email))
+            .await
+            .unwrap()
+            .unwrap_or_else(|_| panic!("{} could not be saved", email))
+    }
+
+    fn emails(contacts: &[Contact]) -> Vec<&str> {
+        contacts.iter().map(Contact::email).collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_saves_get_unique_sequential_ids() {
+        const N: u32 = 50;
+        let repo = Arc::new(repo().await);
+
+        let saves: Vec<_> = (0..N)
+            .map(|i| {
+                let repo = repo.clone();
+                tokio::spawn(async move {
+                    save(
+                        &repo,
+                        "Ada",
+                        &i.to_string(),
+                        &format!("ada{}@example.com", i),
+                    )
+                    .await
+                })
+            })
+            .collect();
+        let mut ids = Vec::new();
+        for save in saves {
+            ids.push(save.await.unwrap().value());
+        }
+
+        ids.sort();
+        assert_eq!(ids, (ids[0]..ids[0] + N).collect::<Vec<_>>());
+        assert_eq!(repo.count().await.unwrap(), N);
+    }
+
+    #[tokio::test]
+    async fn update_to_a_taken_email() {
+        let repo = repo().await;
+        save(&repo, "Ada", "Lovelace", "ada@example.com").await;
+        let id = save(&repo, "Alan", "Turing", "alan@example.com").await;
+
+        let updated = Contact::builder()
+            .id(Some(id))
+            .first("Alan".to_string())
+            .last("Turing".to_string())
+            .phone("".to_string())
+            .email("ada@example.com".to_string())
+            .build();
+        let errors = repo.update(&updated).await.unwrap().unwrap_err();
+        assert_eq!(errors.email.as_deref(), Some(ERR_EMAIL_UNIQUE));
+
+        let alan = repo.find(id).await.unwrap().unwrap();
+        assert_eq!(alan.email(), "alan@example.com");
+    }
+
+    #[tokio::test]
+    async fn pages_follow_the_ids() {
+        let repo = repo().await;
+        let mut ids = Vec::new();
+        for i in 0..6 {
+            let id = save(
+                &repo,
+                "Ada",
+                &i.to_string(),
+                &format!("ada{}@example.com", i),
+            )
+            .await;
+            ids.push(id.value());
+        }
+        let page = |page, page_size| {
+            let repo = &repo;
+            async move {
+                let page = repo
+                    .page_with_neighbors(page, page_size, None, None)
+                    .await
+                    .unwrap();
+                let ids: Vec<_> = page
+                    .contacts
+                    .iter()
+                    .map(|contact| contact.id().unwrap().value())
+                    .collect();
+                (ids, page.has_previous, page.has_next)
+            }
+        };
+
+        // Exactly full: there is one more contact fetched to tell, but none.
+        assert_eq!(page(1, 6).await, (ids.clone(), false, false));
+        assert_eq!(page(1, 3).await, (ids[..3].to_vec(), false, true));
+        assert_eq!(page(2, 3).await, (ids[3..].to_vec(), true, false));
+        // The last page, only partly filled.
+        assert_eq!(page(2, 4).await, (ids[4..].to_vec(), true, false));
+        // Past the last page.
+        assert_eq!(page(3, 3).await, (vec![], true, false));
+    }
+
+    #[tokio::test]
+    async fn pages_of_no_contacts() {
+        let repo = repo().await;
+        let page = repo.page_with_neighbors(1, 10, None, None).await.unwrap();
+        assert!(page.contacts.is_empty());
+        assert!(!page.has_previous);
+        assert!(!page.has_next);
+    }
+
+    #[tokio::test]
+    async fn search_ignores_accents_and_case() {
+        let repo = repo().await;
+        save(&repo, "José", "Álvarez", "jose@example.com").await;
+        save(&repo, "Joseph", "Smith", "joseph@example.com").await;
+        save(&repo, "Ada", "Lovelace", "ada@example.com").await;
+
+        for q in ["jose", "JOSÉ", "José"] {
+            let found = repo.search(Some(q), None, 0, 10).await.unwrap();
+            let mut found = emails(&found);
+            found.sort();
+            assert_eq!(found, ["jose@example.com", "joseph@example.com"], "{}", q);
+            assert_eq!(
+                repo.count_matching(Some(q), None).await.unwrap(),
+                2,
+                "{}",
+                q
+            );
+        }
+
+        let found = repo.search(Some("alvarez"), None, 0, 10).await.unwrap();
+        assert_eq!(emails(&found), ["jose@example.com"]);
+        assert_eq!(repo.count_matching(Some("alvarez"), None).await.unwrap(), 1);
     }
 }
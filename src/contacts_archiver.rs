@@ -1,11 +1,17 @@
 use std::{
-    sync::{atomic::AtomicU8, Arc},
-    time::Duration,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{
+        atomic::{AtomicU32, AtomicU64, AtomicU8},
+        Arc, Mutex,
+    },
 };
 
 use arc_swap::ArcSwapOption;
+use serde::Deserialize;
+use tokio::sync::Notify;
+use tokio_stream::StreamExt;
 
-use crate::contact_repo::ContactRepo;
+use crate::{contact_model::Contact, contact_repo::ContactRepo, text_response::TextType};
 
 #[atomic_enum::atomic_enum]
 #[derive(PartialEq)]
@@ -15,21 +21,166 @@ pub enum Status {
     Complete,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFormat {
+    #[default]
+    Json,
+    Csv,
+    Vcard,
+}
+impl ArchiveFormat {
+    pub fn text_type(&self) -> TextType {
+        match self {
+            Self::Json => TextType::Json,
+            Self::Csv => TextType::Csv,
+            Self::Vcard => TextType::Vcard,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Csv => "csv",
+            Self::Vcard => "vcf",
+        }
+    }
+}
+
+/// The header row of a CSV archive, which is written by serializing the first
+/// contact, or by hand if there is none.
+const CSV_HEADER: [&str; 5] = ["id", "first", "last", "phone", "email"];
+
+/// Builds the data of an archive one contact at a time.
+enum ArchiveWriter {
+    Json {
+        data: String,
+        is_empty: bool,
+    },
+    Csv {
+        writer: Box<csv::Writer<Vec<u8>>>,
+        is_empty: bool,
+    },
+    Vcard(String),
+}
+impl ArchiveWriter {
+    fn new(format: ArchiveFormat) -> Self {
+        match format {
+            ArchiveFormat::Json => Self::Json {
+                data: String::from("["),
+                is_empty: true,
+            },
+            ArchiveFormat::Csv => Self::Csv {
+                writer: Box::new(csv::Writer::from_writer(vec![])),
+                is_empty: true,
+            },
+            ArchiveFormat::Vcard => Self::Vcard(String::new()),
+        }
+    }
+
+    fn push(&mut self, contact: &Contact) {
+        match self {
+            Self::Json { data, is_empty } => {
+                if !*is_empty {
+                    data.push(',');
+                }
+                *is_empty = false;
+                *data += &serde_json::to_string(contact).unwrap();
+            }
+            Self::Csv { writer, is_empty } => {
+                *is_empty = false;
+                writer.serialize(contact).unwrap();
+            }
+            Self::Vcard(data) => *data += &contact.to_vcard(),
+        }
+    }
+
+    fn finish(self) -> String {
+        match self {
+            Self::Json { mut data, .. } => {
+                data.push(']');
+                data
+            }
+            Self::Csv {
+                mut writer,
+                is_empty,
+            } => {
+                if is_empty {
+                    writer.write_record(CSV_HEADER).unwrap();
+                }
+                String::from_utf8(writer.into_inner().unwrap()).unwrap()
+            }
+            Self::Vcard(data) => data,
+        }
+    }
+}
+
+/// Fills `{date}` (as `YYYY-MM-DD`) and `{ext}` in `template`, then strips
+/// everything but ASCII letters, digits, `.`, `-` and `_`, so that the result
+/// can be quoted in a `Content-Disposition` header as is. Runs of dots are
+/// collapsed and leading ones dropped, so that no `..` or hidden file is left.
+pub fn resolve_file_name(template: &str, date: time::Date, format: ArchiveFormat) -> String {
+    let mut file_name = String::new();
+    for c in template
+        .replace("{date}", &date.to_string())
+        .replace("{ext}", format.extension())
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || ".-_".contains(*c))
+    {
+        if c == '.' && (file_name.is_empty() || file_name.ends_with('.')) {
+            continue;
+        }
+        file_name.push(c);
+    }
+
+    if file_name.trim_matches('.').is_empty() {
+        format!("archive.{}", format.extension())
+    } else {
+        file_name
+    }
+}
+
+/// A completed archive.
+pub struct Archive {
+    pub format: ArchiveFormat,
+    pub data: String,
+}
+
 pub struct Archiver {
     contacts: Arc<ContactRepo>,
+    /// How many times the progress is updated during a run.
+    steps: u8,
 
     status: AtomicStatus,
     progress_percentage: AtomicU8,
-    json_data: ArcSwapOption<String>,
+    archive: ArcSwapOption<Archive>,
+    /// How many contacts were there when `archive` was produced.
+    archived_count: AtomicU32,
+    /// The [`ContactRepo::revision`] `archive` was produced from.
+    archived_revision: AtomicU64,
+    /// Hash of the data of `archive`.
+    data_hash: AtomicU64,
+    /// Bumped by every run and reset, so that a task left over from an
+    /// earlier run can tell it is stale and stop writing. Held while a run
+    /// writes, so that no reset can come between its check and its write.
+    generation: Mutex<u64>,
+    /// Notified whenever a run completes or is reset.
+    run_ended: Notify,
 }
 
 impl Archiver {
-    pub fn new(contacts: Arc<ContactRepo>) -> Self {
+    pub fn new(contacts: Arc<ContactRepo>, steps: u8) -> Self {
         Self {
             contacts,
+            steps: steps.clamp(1, 100),
             status: AtomicStatus::new(Status::Waiting),
             progress_percentage: AtomicU8::new(0),
-            json_data: ArcSwapOption::from(None),
+            archive: ArcSwapOption::from(None),
+            archived_count: AtomicU32::new(0),
+            archived_revision: AtomicU64::new(0),
+            data_hash: AtomicU64::new(0),
+            generation: Mutex::new(0),
+            run_ended: Notify::new(),
         }
     }
 
@@ -43,51 +194,381 @@ impl Archiver {
             / 100.0
     }
 
-    pub fn json_data(&self) -> Option<Arc<String>> {
-        self.json_data.load_full()
+    pub fn archive(&self) -> Option<Arc<Archive>> {
+        self.archive.load_full()
+    }
+
+    pub fn archived_count(&self) -> u32 {
+        self.archived_count
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub fn data_hash(&self) -> u64 {
+        self.data_hash.load(std::sync::atomic::Ordering::Relaxed)
     }
 
-    pub fn run(self: &Arc<Self>) {
-        let old_status = self
-            .status
-            .swap(Status::Running, std::sync::atomic::Ordering::Relaxed);
-        if old_status != Status::Waiting {
-            if old_status == Status::Complete {
-                self.status
-                    .store(Status::Complete, std::sync::atomic::Ordering::Relaxed);
+    /// Whether the contacts have changed since the completed archive was made,
+    /// going by their [`ContactRepo::revision`], which is `current_revision`.
+    pub fn is_stale(&self, current_revision: u64) -> bool {
+        self.status() == Status::Complete
+            && self
+                .archived_revision
+                .load(std::sync::atomic::Ordering::Relaxed)
+                != current_revision
+    }
+
+    /// Waits until no run is in progress, which is right away if none is.
+    pub async fn wait_until_idle(&self) {
+        loop {
+            let run_ended = self.run_ended.notified();
+            tokio::pin!(run_ended);
+            // Registers for the notification before checking the status, so
+            // that a run ending in between is not missed.
+            run_ended.as_mut().enable();
+            if self.status() != Status::Running {
+                return;
             }
+            run_ended.await;
+        }
+    }
+
+    /// Starts archiving in the background. If there are no contacts, there is
+    /// nothing to wait for, so the empty archive is completed right away.
+    ///
+    /// A completed archive in another format is thrown away and started over,
+    /// rather than served in the wrong format.
+    pub async fn run(self: &Arc<Self>, format: ArchiveFormat) {
+        let generation = {
+            // Held from the status transition on, so that a reset comes
+            // either before the run starts or after, as a reset of the run.
+            let mut generation = self.generation.lock().unwrap();
+            let old_status = self
+                .status
+                .swap(Status::Running, std::sync::atomic::Ordering::Relaxed);
+            match old_status {
+                Status::Waiting => {}
+                Status::Running => return,
+                Status::Complete => {
+                    if self
+                        .archive()
+                        .is_some_and(|archive| archive.format == format)
+                    {
+                        self.status
+                            .store(Status::Complete, std::sync::atomic::Ordering::Relaxed);
+                        return;
+                    }
+                }
+            }
+            *generation += 1;
+            self.archive.store(None);
+            self.progress_percentage
+                .store(0, std::sync::atomic::Ordering::Relaxed);
+            *generation
+        };
+
+        // Taken first, so that a change made while archiving makes the
+        // archive stale, even if it ends up in it.
+        let revision = self.contacts.revision().await.unwrap();
+        let total = self.contacts.count().await.unwrap();
+        if total == 0 {
+            self.while_current(generation, || {
+                self.progress_percentage
+                    .store(100, std::sync::atomic::Ordering::Relaxed);
+                self.complete(format, ArchiveWriter::new(format).finish(), 0, revision);
+            });
             return;
         }
-        self.progress_percentage
-            .store(0, std::sync::atomic::Ordering::Relaxed);
 
         let archiver = self.clone();
         tokio::spawn(async move {
-            for i in 0..10 {
-                tokio::time::sleep(Duration::from_secs_f64(rand::random())).await;
-                if archiver.status() != Status::Running {
+            let mut writer = ArchiveWriter::new(format);
+            let mut archived = 0;
+            let mut progress = SteppedProgress::new(archiver.steps, total);
+            let mut contacts = archiver.contacts.all_matching(None);
+            while let Some(contact) = contacts.next().await {
+                writer.push(&contact.unwrap());
+                archived += 1;
+                let percentage = progress.advance(archived);
+                let is_current = archiver.while_current(generation, || {
+                    if let Some(percentage) = percentage {
+                        archiver
+                            .progress_percentage
+                            .store(percentage, std::sync::atomic::Ordering::Relaxed);
+                    }
+                });
+                if !is_current {
                     return;
                 }
-                archiver
-                    .progress_percentage
-                    .store((i + 1) * 10, std::sync::atomic::Ordering::Relaxed);
             }
-            tokio::time::sleep(Duration::from_secs(1)).await;
-            if archiver.status() != Status::Running {
-                return;
-            }
-            archiver.json_data.store(Some(Arc::new({
-                let contacts = archiver.contacts.all().await.unwrap();
-                serde_json::to_string(&contacts).unwrap()
-            })));
-            archiver
-                .status
-                .store(Status::Complete, std::sync::atomic::Ordering::Relaxed)
+            let data = writer.finish();
+            archiver.while_current(generation, || {
+                archiver.complete(format, data, archived, revision);
+            });
         });
     }
 
+    fn complete(&self, format: ArchiveFormat, data: String, archived_count: u32, revision: u64) {
+        let mut hasher = DefaultHasher::new();
+        data.hash(&mut hasher);
+        self.archived_count
+            .store(archived_count, std::sync::atomic::Ordering::Relaxed);
+        self.archived_revision
+            .store(revision, std::sync::atomic::Ordering::Relaxed);
+        self.data_hash
+            .store(hasher.finish(), std::sync::atomic::Ordering::Relaxed);
+        self.archive.store(Some(Arc::new(Archive { format, data })));
+        self.status
+            .store(Status::Complete, std::sync::atomic::Ordering::Relaxed);
+        self.run_ended.notify_waiters();
+    }
+
+    /// Calls `write` if no other run or reset has happened since the run of
+    /// `generation` started, keeping any from happening until it returns.
+    /// Returns whether it did.
+    fn while_current(&self, generation: u64, write: impl FnOnce()) -> bool {
+        let current = self.generation.lock().unwrap();
+        if *current != generation {
+            return false;
+        }
+        write();
+        true
+    }
+
     pub fn reset(&self) {
+        let mut generation = self.generation.lock().unwrap();
+        *generation += 1;
+        self.archive.store(None);
         self.status
             .store(Status::Waiting, std::sync::atomic::Ordering::Relaxed);
+        self.run_ended.notify_waiters();
+    }
+}
+
+/// The progress of a run, which only moves in `steps` steps of `100 / steps`
+/// percent each.
+struct SteppedProgress {
+    steps: u64,
+    total: u64,
+    percentage: u8,
+}
+impl SteppedProgress {
+    fn new(steps: u8, total: u32) -> Self {
+        Self {
+            steps: steps.clamp(1, 100) as u64,
+            total: total as u64,
+            percentage: 0,
+        }
+    }
+
+    /// The new percentage once `archived` contacts are archived, if that
+    /// crosses the next step.
+    fn advance(&mut self, archived: u32) -> Option<u8> {
+        let archived = archived as u64;
+        // Contacts may be added while archiving.
+        let step = archived * self.steps / self.total.max(archived);
+        let percentage = (step * 100 / self.steps) as u8;
+        if percentage > self.percentage {
+            self.percentage = percentage;
+            Some(percentage)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The percentages a run over `total` contacts stores, when `added` more
+    /// contacts show up while archiving.
+    fn updates(steps: u8, total: u32, added: u32) -> Vec<u8> {
+        let mut progress = SteppedProgress::new(steps, total);
+        (1..=total + added)
+            .filter_map(|archived| progress.advance(archived))
+            .collect()
+    }
+
+    #[test]
+    fn progress_is_updated_once_per_step() {
+        assert_eq!(
+            updates(10, 1000, 0),
+            [10, 20, 30, 40, 50, 60, 70, 80, 90, 100]
+        );
+        assert_eq!(updates(4, 10, 0), [25, 50, 75, 100]);
+        assert_eq!(updates(1, 50, 0), [100]);
+        assert_eq!(updates(100, 1000, 0).len(), 100);
+    }
+
+    #[test]
+    fn progress_skips_steps_with_few_contacts() {
+        assert_eq!(updates(10, 3, 0), [30, 60, 100]);
+    }
+
+    #[test]
+    fn progress_never_goes_backwards_nor_past_100() {
+        let updates = updates(10, 10, 5);
+        assert!(updates.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(updates.last(), Some(&100));
+    }
+
+    fn date() -> time::Date {
+        time::Date::from_calendar_date(2024, time::Month::March, 9).unwrap()
+    }
+
+    #[test]
+    fn resolve_file_name_fills_in_the_date_and_extension() {
+        assert_eq!(
+            resolve_file_name("contacts-{date}.{ext}", date(), ArchiveFormat::Csv),
+            "contacts-2024-03-09.csv",
+        );
+        assert_eq!(
+            resolve_file_name("archive.{ext}", date(), ArchiveFormat::Vcard),
+            "archive.vcf",
+        );
+        assert_eq!(
+            resolve_file_name("{date}.{ext}.{ext}", date(), ArchiveFormat::Json),
+            "2024-03-09.json.json",
+        );
+    }
+
+    #[test]
+    fn resolve_file_name_strips_what_could_escape_the_name() {
+        for (template, file_name) in [
+            ("../../etc/passwd", "etcpasswd"),
+            ("dir/contacts.{ext}", "dircontacts.json"),
+            ("my contacts {date}.{ext}", "mycontacts2024-03-09.json"),
+            ("a..b...{ext}", "a.b.json"),
+            (".hidden.{ext}", "hidden.json"),
+            (r#"quote"s\.{ext}"#, "quotes.json"),
+        ] {
+            assert_eq!(
+                resolve_file_name(template, date(), ArchiveFormat::Json),
+                file_name,
+                "{}",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn resolve_file_name_falls_back_when_nothing_is_left() {
+        for template in ["", "..", "/ /", "日本語"] {
+            assert_eq!(
+                resolve_file_name(template, date(), ArchiveFormat::Csv),
+                "archive.csv",
+                "{}",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn empty_csv_archive_has_the_header_row() {
+        let mut writer = ArchiveWriter::new(ArchiveFormat::Csv);
+        writer.push(&Contact::new_fake());
+        let data = writer.finish();
+        let header = data.lines().next().unwrap();
+
+        assert_eq!(
+            ArchiveWriter::new(ArchiveFormat::Csv).finish(),
+            format!("{}\n", header),
+        );
+    }
+
+    #[tokio::test]
+    async fn archiving_no_contacts_completes_right_away() {
+        let contacts = Arc::new(ContactRepo::connect("sqlite::memory:").await.unwrap());
+        let archiver = Arc::new(Archiver::new(contacts, 10));
+
+        for format in [
+            ArchiveFormat::Json,
+            ArchiveFormat::Csv,
+            ArchiveFormat::Vcard,
+        ] {
+            archiver.reset();
+            archiver.run(format).await;
+            assert!(archiver.status() == Status::Complete);
+            assert_eq!(archiver.progress(), 1.0);
+            assert_eq!(archiver.archived_count(), 0);
+            assert!(archiver.archive().unwrap().format == format);
+        }
+    }
+
+    #[tokio::test]
+    async fn archive_is_stale_once_any_contact_changes() {
+        let contacts = Arc::new(ContactRepo::connect("sqlite::memory:").await.unwrap());
+        contacts.seed_fake_data(5).await.unwrap();
+        let archiver = Arc::new(Archiver::new(contacts.clone(), 10));
+        assert!(!archiver.is_stale(contacts.revision().await.unwrap()));
+
+        archiver.run(ArchiveFormat::Json).await;
+        archiver.wait_until_idle().await;
+        assert!(!archiver.is_stale(contacts.revision().await.unwrap()));
+
+        // Changes the data while the number of contacts stays the same.
+        let contact = contacts.all().await.unwrap().remove(0);
+        let contact = Contact::builder()
+            .first("Changed".to_string())
+            .last(contact.last().to_string())
+            .phone(contact.phone().to_string())
+            .email(contact.email().to_string())
+            .build()
+            .with_id(contact.id().unwrap());
+        assert!(contacts.update(&contact).await.unwrap().is_ok());
+        assert!(archiver.is_stale(contacts.revision().await.unwrap()));
+
+        archiver.reset();
+        archiver.run(ArchiveFormat::Json).await;
+        archiver.wait_until_idle().await;
+        assert!(!archiver.is_stale(contacts.revision().await.unwrap()));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn restarting_after_reset_keeps_the_latest_run() {
+        let contacts = Arc::new(ContactRepo::connect("sqlite::memory:").await.unwrap());
+        contacts.seed_fake_data(200).await.unwrap();
+        let total = contacts.count().await.unwrap();
+        let archiver = Arc::new(Archiver::new(contacts, 10));
+
+        for _ in 0..20 {
+            archiver.run(ArchiveFormat::Json).await;
+            archiver.reset();
+            archiver.run(ArchiveFormat::Csv).await;
+            archiver.wait_until_idle().await;
+            // Gives the run that was reset the chance to write, which it
+            // must not take.
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+            assert!(archiver.status() == Status::Complete);
+            let archive = archiver.archive().unwrap();
+            assert!(archive.format == ArchiveFormat::Csv);
+            assert_eq!(archive.data.lines().count(), 1 + total as usize);
+            assert_eq!(archiver.archived_count(), total);
+            assert_eq!(archiver.progress(), 1.0);
+
+            // A reset while a run is starting, here one that would keep the
+            // archive, lands either before or after the start.
+            let resetting = {
+                let archiver = archiver.clone();
+                tokio::spawn(async move { archiver.reset() })
+            };
+            archiver.run(ArchiveFormat::Csv).await;
+            resetting.await.unwrap();
+            archiver.wait_until_idle().await;
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+            match archiver.status() {
+                Status::Waiting => assert!(archiver.archive().is_none()),
+                Status::Complete => {
+                    let archive = archiver.archive().unwrap();
+                    assert!(archive.format == ArchiveFormat::Csv);
+                    assert_eq!(archive.data.lines().count(), 1 + total as usize);
+                }
+                Status::Running => unreachable!(),
+            }
+
+            archiver.reset();
+        }
     }
 }
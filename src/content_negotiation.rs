@@ -0,0 +1,78 @@
+use std::convert::Infallible;
+
+use axum::{
+    async_trait,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{header, request::Parts, HeaderMap},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::Form;
+use serde::de::DeserializeOwned;
+
+/// Whether the client asked for JSON rather than HTML through the `Accept`
+/// header.
+///
+/// Browsers and htmx always accept HTML one way or another, so only clients
+/// that mention `application/json` without `text/html` get JSON.
+pub struct AcceptsJson(pub bool);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AcceptsJson {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let accept = parts
+            .headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|media_range| media_range.split(';').next().unwrap_or("").trim());
+
+        let mut accepts_json = false;
+        for media_type in accept {
+            match media_type {
+                "text/html" => return Ok(Self(false)),
+                "application/json" => accepts_json = true,
+                _ => {}
+            }
+        }
+        Ok(Self(accepts_json))
+    }
+}
+
+/// Extracts `T` from a JSON body if the request says it has one, or from a
+/// form otherwise.
+pub struct JsonOrForm<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for JsonOrForm<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if has_json_content_type(req.headers()) {
+            let Json(value) = Json::<T>::from_request(req, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            Ok(Self(value))
+        } else {
+            let Form(value) = Form::<T>::from_request(req, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            Ok(Self(value))
+        }
+    }
+}
+
+fn has_json_content_type(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|mime| mime.trim() == "application/json")
+}
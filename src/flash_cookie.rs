@@ -0,0 +1,53 @@
+use axum::{
+    extract::Request,
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+/// The name `axum_flash` gives its cookie.
+const FLASH_COOKIE_NAME: &str = "axum-flash";
+
+/// Relaxes the flash cookie from `SameSite=Strict` to `SameSite=Lax`.
+///
+/// Safari does not reliably send `SameSite=Strict` cookies along with the
+/// request that follows a redirect, so the flashes set right before
+/// redirecting, like "Created New Contact!", never showed up there.
+/// `axum_flash` hard-codes the attribute, hence the rewrite. The rest of the
+/// cookie is already right: its path is `/`, and it is only `Secure` where
+/// [`crate::config::Environment::use_secure_cookies`] says so, which is not
+/// the case on plain-HTTP localhost.
+pub async fn relax_same_site(req: Request, next: Next) -> Response {
+    let mut res = next.run(req).await;
+
+    let headers = res.headers_mut();
+    let set_cookies: Vec<_> = headers
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .cloned()
+        .collect();
+    if !set_cookies.iter().any(is_flash_cookie) {
+        return res;
+    }
+
+    headers.remove(header::SET_COOKIE);
+    for set_cookie in set_cookies {
+        let set_cookie = match set_cookie.to_str() {
+            Ok(value) if is_flash_cookie(&set_cookie) => {
+                HeaderValue::from_str(&value.replace("SameSite=Strict", "SameSite=Lax"))
+                    .unwrap_or(set_cookie)
+            }
+            _ => set_cookie,
+        };
+        headers.append(header::SET_COOKIE, set_cookie);
+    }
+
+    res
+}
+
+fn is_flash_cookie(set_cookie: &HeaderValue) -> bool {
+    set_cookie
+        .as_bytes()
+        .strip_prefix(FLASH_COOKIE_NAME.as_bytes())
+        .is_some_and(|rest| rest.starts_with(b"="))
+}
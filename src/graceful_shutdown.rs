@@ -0,0 +1,104 @@
+//! `axum::serve` only learns to shut down gracefully in axum 0.7.3, so this
+//! serves connections with hyper directly, the way axum's `graceful-shutdown`
+//! example does for 0.7.2.
+
+use std::{future::Future, time::Duration};
+
+use axum::{
+    extract::{ConnectInfo, Request},
+    Router,
+};
+use hyper::{body::Incoming, server::conn::http1};
+use hyper_util::rt::TokioIo;
+use tokio::{net::TcpListener, sync::watch};
+use tower::Service;
+
+/// Serves `app` on `listener` until `signal` resolves, then stops accepting
+/// connections and gives the open ones up to `timeout` to finish the requests
+/// they are in the middle of.
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    signal: impl Future<Output = ()>,
+    timeout: Duration,
+) {
+    // Every connection holds a receiver, so the sender is closed once they
+    // are all done.
+    let (close_tx, close_rx) = watch::channel(());
+
+    tokio::pin!(signal);
+    loop {
+        let (socket, remote_addr) = tokio::select! {
+            result = listener.accept() => match result {
+                Ok(conn) => conn,
+                Err(err) => {
+                    tracing::error!("accept error: {}", err);
+                    continue;
+                }
+            },
+            _ = &mut signal => break,
+        };
+
+        let app = app.clone();
+        let mut close_rx = close_rx.clone();
+        tokio::spawn(async move {
+            let service = hyper::service::service_fn(move |mut req: Request<Incoming>| {
+                // As `axum::serve` would, for `ConnectInfo` extractors.
+                req.extensions_mut().insert(ConnectInfo(remote_addr));
+                app.clone().call(req)
+            });
+            let conn = http1::Builder::new().serve_connection(TokioIo::new(socket), service);
+            tokio::pin!(conn);
+            let result = tokio::select! {
+                result = conn.as_mut() => result,
+                // Lets the request in progress, if any, complete, then
+                // closes the connection instead of keeping it alive. This
+                // also happens if `serve` has given up on the connection and
+                // dropped the sender, and only needs to happen once.
+                _ = close_rx.changed() => {
+                    conn.as_mut().graceful_shutdown();
+                    conn.as_mut().await
+                }
+            };
+            if let Err(err) = result {
+                tracing::debug!("connection error: {}", err);
+            }
+            drop(close_rx);
+        });
+    }
+
+    drop(close_rx);
+    drop(listener);
+    tracing::info!("shutting down");
+
+    // Nobody is left to notify if there are no connections.
+    let _ = close_tx.send(());
+    if tokio::time::timeout(timeout, close_tx.closed())
+        .await
+        .is_err()
+    {
+        tracing::warn!("gave up waiting for in-flight requests");
+    }
+}
+
+/// Resolves on Ctrl-C, or on `SIGTERM` where there is such a thing.
+pub async fn signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.unwrap();
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .unwrap()
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
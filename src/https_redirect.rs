@@ -0,0 +1,32 @@
+use axum::{
+    extract::Request,
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+
+/// Redirects requests that reached the reverse proxy over plain HTTP to their
+/// HTTPS counterpart, as reported by the `X-Forwarded-Proto` header.
+///
+/// NOTE: The header is taken at face value, so this is only enabled with
+/// [`crate::config::AppConfig::behind_proxy`], when the app is reachable
+/// through the proxy alone.
+pub async fn redirect_to_https(req: Request, next: Next) -> Response {
+    let headers = req.headers();
+    let forwarded_proto = headers
+        .get("x-forwarded-proto")
+        .and_then(|v| v.to_str().ok());
+    if !forwarded_proto.is_some_and(|proto| proto.eq_ignore_ascii_case("http")) {
+        return next.run(req).await;
+    }
+    let Some(host) = headers.get(header::HOST).and_then(|v| v.to_str().ok()) else {
+        return next.run(req).await;
+    };
+
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    Redirect::permanent(&format!("https://{}{}", host, path_and_query)).into_response()
+}
@@ -1,44 +1,130 @@
 use std::sync::Arc;
 
 use axum::{
-    extract::Request,
+    extract::{Request, State},
+    http::header,
     middleware::Next,
-    response::{Html, Response},
+    response::{IntoResponse, Response},
     RequestExt,
 };
 use axum_flash::IncomingFlashes;
 use axum_htmx::{HxBoosted, HxRequest};
+use serde::{Deserialize, Serialize};
+
+use crate::text_response::Html;
 
 #[derive(Clone)]
 pub struct Layouter(pub LayouterInner);
 
 pub type LayouterInner =
-    Arc<dyn Fn(IncomingFlashes, markup::DynRender) -> Html<String> + Send + Sync + 'static>;
+    Arc<dyn Fn(IncomingFlashes, markup::DynRender) -> Html + Send + Sync + 'static>;
+
+const DEMO_BANNER_DISMISSED_COOKIE: &str = "demo_banner_dismissed=1";
+
+#[derive(Clone)]
+pub struct LayoutConfig {
+    /// Whether the contacts are seeded fake ones, in which case a banner says
+    /// so until it is dismissed.
+    pub demo_data: bool,
+    /// For the flashes on error pages, which no handler extracts.
+    pub flash_config: axum_flash::Config,
+}
 
-pub async fn with_layouter(mut req: Request, next: Next) -> Response {
+/// Marks a response whose body is to be replaced by an error page, laid out
+/// like any other page, by [`with_layouter`].
+#[derive(Clone)]
+pub struct ErrorPage;
+
+pub async fn with_layouter(
+    State(config): State<LayoutConfig>,
+    mut req: Request,
+    next: Next,
+) -> Response {
     let HxRequest(is_htmx_request) = req.extract_parts::<HxRequest>().await.unwrap();
     let HxBoosted(is_htmx_boosted) = req.extract_parts::<HxBoosted>().await.unwrap();
 
-    let layouter = if is_htmx_request && !is_htmx_boosted {
+    let demo_banner = config.demo_data && !is_demo_banner_dismissed(&req);
+
+    let is_fragment = is_htmx_request && !is_htmx_boosted;
+    let layouter = if is_fragment {
         Layouter(Arc::new(|flashes, content| {
             Html(layouts::Minimum { flashes, content }.to_string())
         }))
     } else {
-        Layouter(Arc::new(|flashes, content| {
-            Html(layouts::Default { flashes, content }.to_string())
+        Layouter(Arc::new(move |flashes, content| {
+            Html(
+                layouts::Default {
+                    flashes,
+                    content,
+                    demo_banner,
+                }
+                .to_string(),
+            )
         }))
     };
 
-    req.extensions_mut().insert(layouter);
+    let flashes = req
+        .extract_parts_with_state::<IncomingFlashes, _>(&config.flash_config)
+        .await
+        .unwrap();
+
+    req.extensions_mut().insert(layouter.clone());
+
+    let res = next.run(req).await;
+    if res.extensions().get::<ErrorPage>().is_none() {
+        return res;
+    }
+
+    let Layouter(layouter) = layouter;
+    let rendered = layouter(
+        flashes.clone(),
+        markup::new!(@layouts::ErrorContent { is_fragment }),
+    );
+    (res.status(), flashes, rendered).into_response()
+}
+
+/// A flash message with a button that posts to `action`, for example to undo
+/// what the message reports.
+///
+/// Flash messages are plain text, so this is carried in one as JSON, which
+/// [`Self::flash_message`] encodes and the layouts decode.
+#[derive(Serialize, Deserialize)]
+pub struct FlashAction {
+    pub message: String,
+    pub label: String,
+    pub action: String,
+    /// Posted along as hidden fields, for example the ids of the contacts the
+    /// action is about.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<(String, String)>,
+}
+
+impl FlashAction {
+    pub fn flash_message(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+/// Shows `message` as the only flash, swapped into the page out of band, for
+/// htmx responses whose target is something other than the content.
+pub fn out_of_band_flash(message: &str) -> String {
+    layouts::OutOfBandFlash { message }.to_string()
+}
 
-    next.run(req).await
+fn is_demo_banner_dismissed(req: &Request) -> bool {
+    req.headers()
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .any(|cookie| cookie.trim() == DEMO_BANNER_DISMISSED_COOKIE)
 }
 
 mod layouts {
     use axum_flash::IncomingFlashes;
 
     markup::define! {
-        Default<T: markup::Render>(flashes: IncomingFlashes, content: T) {
+        Default<T: markup::Render>(flashes: IncomingFlashes, content: T, demo_banner: bool) {
             @markup::doctype()
             html {
                 head {
@@ -55,10 +141,22 @@ mod layouts {
                     link [rel="stylesheet", href="/static/site.css"];
                 }
                 body ["hx-boost"="true"] {
+                    @if *demo_banner {
+                        div #"demo-banner" {
+                            "Showing demo data. "
+                            button [
+                                type="button",
+                                onclick=format!(
+                                    "document.cookie = '{}; path=/; SameSite=Lax'; this.parentElement.remove()",
+                                    super::DEMO_BANNER_DISMISSED_COOKIE,
+                                ),
+                            ] { "Dismiss" }
+                        }
+                    }
                     main {
                         div #flashes {
                             @for (_, message) in flashes.iter() {
-                                div .flash { @message }
+                                @FlashMessage { message }
                             }
                         }
                         div #content {
@@ -72,10 +170,46 @@ mod layouts {
         Minimum<T: markup::Render>(flashes: IncomingFlashes, content: T) {
             div #flashes["hx-swap-oob"="true"] {
                 @for (_, message) in flashes.iter() {
-                    div .flash { @message }
+                    @FlashMessage { message }
                 }
             }
             @content
         }
+
+        OutOfBandFlash<'a>(message: &'a str) {
+            div #flashes["hx-swap-oob"="true"] {
+                @FlashMessage { message }
+            }
+        }
+
+        ErrorContent(is_fragment: bool) {
+            @if *is_fragment {
+                span .error { "Something went wrong. Please try again later." }
+            } else {
+                h1 { "Something Went Wrong" }
+
+                p { "Sorry, something went wrong on our side. Please try again later." }
+
+                p {
+                    a [href="/contacts"] { "Back to Contacts" }
+                }
+            }
+        }
+
+        FlashMessage<'a>(message: &'a str) {
+            @if let Ok(flash_action) = serde_json::from_str::<super::FlashAction>(message) {
+                div .flash {
+                    @flash_action.message " "
+                    form [action=&flash_action.action, method="POST", style="display: inline"] {
+                        @for (name, value) in flash_action.values.iter() {
+                            input [type="hidden", name=name, value=value];
+                        }
+                        button { @flash_action.label }
+                    }
+                }
+            } else {
+                div .flash { @message }
+            }
+        }
     }
 }
@@ -0,0 +1,120 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+/// How many addresses are tracked before the ones whose window has passed
+/// are forgotten.
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// Allows each client address `limit` requests per `period`, counted in
+/// fixed windows.
+///
+/// NOTE: Clients are told apart by the address connecting to the app, so
+/// behind a reverse proxy they all share a single limit.
+#[derive(Clone)]
+pub struct RateLimiter {
+    limit: u32,
+    period: Duration,
+    /// When the current window of each address started, and how many
+    /// requests it made in it.
+    windows: Arc<Mutex<HashMap<IpAddr, (Instant, u32)>>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: u32, period: Duration) -> Self {
+        Self {
+            limit,
+            period,
+            windows: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Counts a request from `ip`. Returns how long until `ip` may make
+    /// requests again if it is over the limit.
+    fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+
+        if windows.len() >= PRUNE_THRESHOLD {
+            windows.retain(|_, (start, _)| now.duration_since(*start) < self.period);
+        }
+
+        let (start, count) = windows.entry(ip).or_insert((now, 0));
+        if now.duration_since(*start) >= self.period {
+            *start = now;
+            *count = 0;
+        }
+        if *count >= self.limit {
+            return Err(self.period - now.duration_since(*start));
+        }
+        *count += 1;
+
+        Ok(())
+    }
+}
+
+/// Responds `429 Too Many Requests` to clients over the limit of `limiter`.
+/// Requests with no known client address are let through.
+pub async fn limit_rate(State(limiter): State<RateLimiter>, req: Request, next: Next) -> Response {
+    let Some(ConnectInfo(addr)) = req.extensions().get::<ConnectInfo<SocketAddr>>() else {
+        return next.run(req).await;
+    };
+
+    match limiter.check(addr.ip()) {
+        Ok(()) => next.run(req).await,
+        Err(retry_after) => (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(
+                header::RETRY_AFTER,
+                retry_after.as_secs().max(1).to_string(),
+            )],
+        )
+            .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    const OTHER_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+
+    #[test]
+    fn allows_limit_requests_then_refuses() {
+        let period = Duration::from_secs(60);
+        let limiter = RateLimiter::new(3, period);
+
+        for _ in 0..3 {
+            assert_eq!(limiter.check(IP), Ok(()));
+        }
+        let retry_after = limiter.check(IP).unwrap_err();
+        assert!(retry_after > Duration::ZERO && retry_after <= period);
+        assert!(limiter.check(IP).is_err());
+
+        // Every address has a limit of its own.
+        assert_eq!(limiter.check(OTHER_IP), Ok(()));
+    }
+
+    #[test]
+    fn allows_requests_again_in_the_next_window() {
+        let limiter = RateLimiter::new(1, Duration::from_millis(20));
+
+        assert_eq!(limiter.check(IP), Ok(()));
+        assert!(limiter.check(IP).is_err());
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(limiter.check(IP), Ok(()));
+    }
+}
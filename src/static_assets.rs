@@ -1,26 +1,64 @@
 //! See: <https://github.com/pyrossh/rust-embed/blob/master/examples/axum.rs>.
 
 use axum::{
-    http::{header, StatusCode},
+    http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Response},
 };
 
+/// Assets are not fingerprinted, so they are only cached for a day, after
+/// which the browser revalidates them by their ETags.
+const CACHE_CONTROL: &str = "public, max-age=86400";
+
 #[derive(rust_embed::RustEmbed)]
 #[folder = "static"]
 struct Assets;
 
-pub struct StaticFile<T: Into<String>>(pub T);
+pub struct StaticFile<T: Into<String>> {
+    pub path: T,
+    /// Headers of the request, for `If-None-Match`.
+    pub request_headers: HeaderMap,
+}
 
 impl<T: Into<String>> IntoResponse for StaticFile<T> {
     fn into_response(self) -> Response {
-        let path = self.0.into();
+        let path = self.path.into();
 
         match Assets::get(path.as_str()) {
             Some(content) => {
+                let etag = format!(r#""{}""#, hex(&content.metadata.sha256_hash()));
+                let headers = [
+                    (header::ETAG, etag.clone()),
+                    (header::CACHE_CONTROL, CACHE_CONTROL.to_string()),
+                ];
+
+                if matches_if_none_match(&self.request_headers, &etag) {
+                    return (StatusCode::NOT_MODIFIED, headers).into_response();
+                }
+
                 let mime = mime_guess::from_path(path).first_or_octet_stream();
-                ([(header::CONTENT_TYPE, mime.as_ref())], content.data).into_response()
+                (
+                    headers,
+                    [(header::CONTENT_TYPE, mime.as_ref())],
+                    content.data,
+                )
+                    .into_response()
             }
             None => StatusCode::NOT_FOUND.into_response(),
         }
     }
 }
+
+/// Whether `If-None-Match` lists `etag`, weakly compared, or is `*`.
+fn matches_if_none_match(request_headers: &HeaderMap, etag: &str) -> bool {
+    request_headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
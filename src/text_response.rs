@@ -0,0 +1,49 @@
+use axum::{
+    http::{header, HeaderValue},
+    response::{IntoResponse, IntoResponseParts, Response, ResponseParts},
+};
+
+/// The kinds of text the app responds with, all of it encoded as UTF-8.
+///
+/// As a response part, sets the `Content-Type`, charset included, so that
+/// no response leaves the browser to guess it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextType {
+    Html,
+    Json,
+    Ndjson,
+    Csv,
+    Vcard,
+    Markdown,
+}
+impl TextType {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Html => "text/html; charset=utf-8",
+            Self::Json => "application/json; charset=utf-8",
+            Self::Ndjson => "application/x-ndjson; charset=utf-8",
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Vcard => "text/vcard; charset=utf-8",
+            Self::Markdown => "text/markdown; charset=utf-8",
+        }
+    }
+}
+impl IntoResponseParts for TextType {
+    type Error = std::convert::Infallible;
+
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        res.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(self.content_type()),
+        );
+        Ok(res)
+    }
+}
+
+/// A page or a fragment of one, sent as [`TextType::Html`].
+pub struct Html(pub String);
+impl IntoResponse for Html {
+    fn into_response(self) -> Response {
+        (TextType::Html, self.0).into_response()
+    }
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+
+use contact::build_app;
+
+use common::*;
+
+/// Polls the archive UI until the archive is ready, as the page does.
+async fn wait_for_archive(app: &Router) -> String {
+    for _ in 0..100 {
+        let body = body_text(send(app, get("/contacts/archive")).await).await;
+        if body.contains("Archive Ready!") {
+            return body;
+        }
+        assert!(body.contains("Creating Archive"));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("the archive never became ready");
+}
+
+#[tokio::test]
+async fn archive_lifecycle() {
+    let (app, _) = seeded_app(config()).await;
+
+    let response = send(&app, get("/contacts/archive/file")).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body = body_text(send(&app, get("/contacts/archive")).await).await;
+    assert!(body.contains("Download Contact Archive"));
+
+    let response = send(&app, post_form("/contacts/archive", &[("format", "json")])).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    wait_for_archive(&app).await;
+
+    let response = send(&app, get("/contacts/archive/file")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_DISPOSITION],
+        r#"attachment; filename="archive.json""#,
+    );
+    assert!(response.headers().contains_key(header::ETAG));
+    let archived = body_json(response).await;
+    let mut emails: Vec<_> = archived
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|contact| contact["email"].as_str().unwrap().to_string())
+        .collect();
+    emails.sort();
+    let mut seeded: Vec<_> = SEED_CONTACTS
+        .iter()
+        .map(|(_, _, _, email)| email.to_string())
+        .collect();
+    seeded.sort();
+    assert_eq!(emails, seeded);
+
+    let request = Request::delete("/contacts/archive")
+        .body(Body::empty())
+        .unwrap();
+    let body = body_text(send(&app, request).await).await;
+    assert!(body.contains("Download Contact Archive"));
+    let response = send(&app, get("/contacts/archive/file")).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn archive_in_another_format() {
+    let (app, _) = seeded_app(config()).await;
+
+    send(&app, post_form("/contacts/archive", &[("format", "csv")])).await;
+    wait_for_archive(&app).await;
+
+    let response = send(&app, get("/contacts/archive/file")).await;
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/csv; charset=utf-8",
+    );
+    let csv = body_text(response).await;
+    assert_eq!(csv.lines().count(), 1 + SEED_CONTACTS.len());
+}
+
+#[tokio::test]
+async fn archive_goes_stale_when_a_contact_is_edited() {
+    let (app, _) = seeded_app(config()).await;
+    let id = create_contact(&app, "Barbara", "Liskov", "", "barbara@example.com").await;
+
+    send(&app, post_form("/contacts/archive", &[("format", "json")])).await;
+    let body = wait_for_archive(&app).await;
+    assert!(!body.contains("out of date"));
+
+    // The number of contacts stays the same.
+    let response = send(
+        &app,
+        post_form(
+            &format!("/contacts/{}/edit", id),
+            &[
+                ("first_name", "Barbara"),
+                ("last_name", "Liskov"),
+                ("phone", ""),
+                ("email", "liskov@example.com"),
+            ],
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let body = wait_for_archive(&app).await;
+    assert!(body.contains("Archive may be out of date"));
+}
+
+#[tokio::test]
+async fn archive_of_no_contacts() {
+    let (app, _) = build_app(config()).await;
+
+    for (format, data) in [
+        ("json", "[]"),
+        ("csv", "id,first,last,phone,email\n"),
+        ("vcard", ""),
+    ] {
+        let request = Request::delete("/contacts/archive")
+            .body(Body::empty())
+            .unwrap();
+        send(&app, request).await;
+
+        // Ready in the response to starting it, without polling.
+        let response = send(&app, post_form("/contacts/archive", &[("format", format)])).await;
+        assert!(
+            body_text(response).await.contains("Archive Ready!"),
+            "{}",
+            format
+        );
+
+        let response = send(&app, get("/contacts/archive/file")).await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", format);
+        assert_eq!(body_text(response).await, data, "{}", format);
+    }
+}
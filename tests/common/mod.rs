@@ -0,0 +1,144 @@
+//! Builds the app the way `main` does, but on a fresh in-memory database, and
+//! drives it without binding a socket.
+
+#![allow(dead_code)]
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, Request, StatusCode},
+    response::Response,
+    Router,
+};
+use tower::ServiceExt;
+
+use contact::{
+    build_app,
+    config::{AppConfig, Environment, PhoneLocale},
+    AppState,
+};
+
+/// The contacts every test starts from, as `(first, last, phone, email)`.
+pub const SEED_CONTACTS: [(&str, &str, &str, &str); 5] = [
+    ("Ada", "Lovelace", "555-123-4567", "ada@example.com"),
+    ("Alan", "Turing", "", "alan@example.com"),
+    ("Grace", "Hopper", "(555) 987-6543", "grace@example.com"),
+    ("José", "Álvarez", "", "jose@example.com"),
+    ("Edsger", "Dijkstra", "", "edsger@example.com"),
+];
+
+pub fn config() -> AppConfig {
+    AppConfig {
+        environment: Environment::Development,
+        behind_proxy: false,
+        // Every connection to it opens the same database, which is a new one
+        // for every test.
+        database_url: "sqlite::memory:".to_string(),
+        fake_contacts: 0,
+        archive_steps: 10,
+        archive_file_name: "archive.{ext}".to_string(),
+        phone_locale: PhoneLocale::NorthAmerica,
+        mask_contact_details: false,
+        page_size: 10,
+        validation_rate_limit: 120,
+        import_row_limit: 1000,
+        demo_slow_count: false,
+        count_queries: false,
+    }
+}
+
+/// The app with [`SEED_CONTACTS`] in it.
+pub async fn seeded_app(config: AppConfig) -> (Router, AppState) {
+    let (app, app_state) = build_app(config).await;
+    for (first, last, phone, email) in SEED_CONTACTS {
+        create_contact(&app, first, last, phone, email).await;
+    }
+    (app, app_state)
+}
+
+pub async fn send(app: &Router, request: Request<Body>) -> Response {
+    app.clone().oneshot(request).await.unwrap()
+}
+
+pub fn get(uri: &str) -> Request<Body> {
+    Request::get(uri).body(Body::empty()).unwrap()
+}
+
+pub fn get_json(uri: &str) -> Request<Body> {
+    Request::get(uri)
+        .header(header::ACCEPT, "application/json")
+        .body(Body::empty())
+        .unwrap()
+}
+
+pub fn post_form(uri: &str, form: &[(&str, &str)]) -> Request<Body> {
+    Request::post(uri)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(encode_form(form)))
+        .unwrap()
+}
+
+pub fn encode_form(form: &[(&str, &str)]) -> String {
+    form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(form)
+        .finish()
+}
+
+pub async fn body_text(response: Response) -> String {
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+pub async fn body_json(response: Response) -> serde_json::Value {
+    serde_json::from_str(&body_text(response).await).unwrap()
+}
+
+pub fn location(response: &Response) -> &str {
+    response.headers()[header::LOCATION].to_str().unwrap()
+}
+
+/// The cookies `response` sets, ready to be sent back with a `Cookie` header,
+/// for example to see the flashes of a redirect.
+pub fn cookies(response: &Response) -> String {
+    response
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .map(|cookie| cookie.to_str().unwrap().split(';').next().unwrap())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Creates a contact through the JSON API. Returns its id.
+pub async fn create_contact(
+    app: &Router,
+    first: &str,
+    last: &str,
+    phone: &str,
+    email: &str,
+) -> u32 {
+    let request = Request::post("/contacts/new")
+        .header(header::ACCEPT, "application/json")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            serde_json::json!({ "first": first, "last": last, "phone": phone, "email": email })
+                .to_string(),
+        ))
+        .unwrap();
+    let response = send(app, request).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    body_json(response).await["id"].as_u64().unwrap() as u32
+}
+
+/// The emails of the contacts `GET` `uri` lists, as JSON.
+pub async fn listed_emails(app: &Router, uri: &str) -> Vec<String> {
+    let response = send(app, get_json(uri)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    body_json(response).await["contacts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|contact| contact["email"].as_str().unwrap().to_string())
+        .collect()
+}
@@ -0,0 +1,807 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+};
+
+use contact::{build_app, config::AppConfig};
+
+use common::*;
+
+#[tokio::test]
+async fn create_list_edit_delete() {
+    let (app, _) = seeded_app(config()).await;
+
+    let response = send(
+        &app,
+        post_form(
+            "/contacts/new",
+            &[
+                ("first_name", "Barbara"),
+                ("last_name", "Liskov"),
+                ("phone", ""),
+                ("email", "barbara@example.com"),
+            ],
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(location(&response), "/contacts");
+
+    let response = send(&app, get("/contacts")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = body_text(response).await;
+    assert!(body.contains("Liskov"));
+    assert!(body.contains("barbara@example.com"));
+
+    let response = send(&app, get_json("/contacts?q=barbara")).await;
+    let id = body_json(response).await["contacts"][0]["id"]
+        .as_u64()
+        .unwrap();
+
+    let response = send(
+        &app,
+        post_form(
+            &format!("/contacts/{}/edit", id),
+            &[
+                ("first_name", "Barbara"),
+                ("last_name", "Liskov"),
+                ("phone", ""),
+                ("email", "liskov@example.com"),
+            ],
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(location(&response), format!("/contacts/{}", id));
+
+    let response = send(&app, get_json(&format!("/contacts/{}", id))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_json(response).await["email"], "liskov@example.com");
+
+    let response = send(&app, post_form(&format!("/contacts/{}/delete", id), &[])).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(location(&response), "/contacts");
+
+    let response = send(&app, get(&format!("/contacts/{}", id))).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body = body_text(send(&app, get("/contacts")).await).await;
+    assert!(!body.contains("Liskov"));
+}
+
+#[tokio::test]
+async fn search_responds_with_only_the_list() {
+    let (app, _) = seeded_app(config()).await;
+
+    let request = Request::get("/contacts?q=hopper")
+        .header("HX-Request", "true")
+        .header("HX-Trigger", "search")
+        .body(Body::empty())
+        .unwrap();
+    let response = send(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()[header::CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .starts_with("text/html"));
+    let body = body_text(response).await;
+    assert!(body.starts_with(r#"<div id="contacts-list">"#));
+    assert!(!body.contains("<html"));
+    assert!(body.contains("grace@example.com"));
+    assert!(!body.contains("ada@example.com"));
+}
+
+#[tokio::test]
+async fn search_fragment_has_the_rows_of_the_full_page() {
+    let (app, _) = seeded_app(AppConfig {
+        page_size: 2,
+        ..config()
+    })
+    .await;
+
+    fn tbody(body: &str) -> &str {
+        let start = body.find("<tbody").unwrap();
+        let end = body.find("</tbody>").unwrap();
+        &body[start..end]
+    }
+
+    for uri in [
+        "/contacts?q=a",
+        "/contacts?q=a&page=2",
+        "/contacts?q=nobody",
+    ] {
+        let request = Request::get(uri)
+            .header("HX-Request", "true")
+            .header("HX-Trigger", "search")
+            .body(Body::empty())
+            .unwrap();
+        let fragment = body_text(send(&app, request).await).await;
+        let page = body_text(send(&app, get(uri)).await).await;
+        assert_eq!(tbody(&fragment), tbody(&page), "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn create() {
+    let (app, _) = seeded_app(config()).await;
+
+    let request = Request::post("/contacts/new")
+        .header(header::ACCEPT, "application/json")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            serde_json::json!({
+                "first": "Barbara",
+                "last": "Liskov",
+                "phone": "(555) 222-3333",
+                "email": "barbara@example.com",
+            })
+            .to_string(),
+        ))
+        .unwrap();
+    let response = send(&app, request).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let contact = body_json(response).await;
+    assert_eq!(contact["first"], "Barbara");
+    assert_eq!(contact["phone"], "+15552223333");
+
+    let response = send(&app, get_json(&format!("/contacts/{}", contact["id"]))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_json(response).await, contact);
+}
+
+#[tokio::test]
+async fn reject_duplicate_email() {
+    let (app, _) = seeded_app(config()).await;
+
+    let response = send(
+        &app,
+        post_form(
+            "/contacts/new",
+            &[
+                ("first_name", "Another"),
+                ("last_name", "Ada"),
+                ("phone", ""),
+                ("email", "ada@example.com"),
+            ],
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(body_text(response).await.contains("Email Must Be Unique"));
+
+    let request = Request::post("/contacts/new")
+        .header(header::ACCEPT, "application/json")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            serde_json::json!({
+                "first": "Another",
+                "last": "Ada",
+                "phone": "",
+                "email": "ada@example.com",
+            })
+            .to_string(),
+        ))
+        .unwrap();
+    let response = send(&app, request).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body_json(response).await["email"], "Email Must Be Unique");
+
+    let emails = listed_emails(&app, "/contacts?q=ada").await;
+    assert_eq!(emails, ["ada@example.com"]);
+}
+
+#[tokio::test]
+async fn reject_duplicate_email_on_edit() {
+    let (app, _) = seeded_app(config()).await;
+    let id = create_contact(&app, "Barbara", "Liskov", "", "barbara@example.com").await;
+
+    let response = send(
+        &app,
+        post_form(
+            &format!("/contacts/{}/edit", id),
+            &[
+                ("first_name", "Barbara"),
+                ("last_name", "Liskov"),
+                ("phone", ""),
+                ("email", "ada@example.com"),
+            ],
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(body_text(response).await.contains("Email Must Be Unique"));
+
+    let response = send(&app, get_json(&format!("/contacts/{}", id))).await;
+    assert_eq!(body_json(response).await["email"], "barbara@example.com");
+}
+
+#[tokio::test]
+async fn edit_in_a_dialog() {
+    let (app, _) = seeded_app(config()).await;
+    let id = create_contact(&app, "Barbara", "Liskov", "", "barbara@example.com").await;
+
+    let body = body_text(send(&app, get("/contacts")).await).await;
+    assert!(body.contains(&format!(r#"hx-get="/contacts/{}/edit?modal=1""#, id)));
+
+    let response = send(&app, get(&format!("/contacts/{}/edit?modal=1", id))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = body_text(response).await;
+    assert!(body.starts_with(r#"<dialog id="edit-contact-dialog""#));
+    assert!(body.contains(&format!(r#"hx-post="/contacts/{}/edit?modal=1""#, id)));
+    assert!(body.contains("barbara@example.com"));
+    assert!(body.contains("Cancel"));
+    assert!(!body.contains("Back"));
+    assert!(!body.contains("delete-btn"));
+
+    let edit = |email: &'static str| {
+        post_form(
+            &format!("/contacts/{}/edit?modal=1", id),
+            &[
+                ("first_name", "Barbara"),
+                ("last_name", "Liskov"),
+                ("phone", ""),
+                ("email", email),
+            ],
+        )
+    };
+
+    let response = send(&app, edit("ada@example.com")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = body_text(response).await;
+    assert!(body.starts_with(r#"<dialog id="edit-contact-dialog""#));
+    assert!(body.contains("Email Must Be Unique"));
+
+    let response = send(&app, edit("liskov@example.com")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["HX-Reswap"], "delete");
+    let body = body_text(response).await;
+    assert!(body.contains(&format!(r#"<tr id="contact-{}" hx-swap-oob="true">"#, id)));
+    assert!(body.contains("liskov@example.com"));
+    assert!(body.contains("Updated Contact!"));
+}
+
+#[tokio::test]
+async fn split_a_full_name() {
+    let (app, _) = seeded_app(config()).await;
+
+    let body = body_text(
+        send(
+            &app,
+            get("/contacts/validate-name?first_name=Ada+Lovelace&last_name="),
+        )
+        .await,
+    )
+    .await;
+    assert!(body.contains("Looks Like a Full Name"));
+    assert!(body.contains(
+        r#"hx-vals="{&quot;first_name&quot;:&quot;Ada&quot;,&quot;last_name&quot;:&quot;Lovelace&quot;,&quot;split&quot;:true}""#
+    ));
+    assert!(!body.contains("onclick"));
+
+    let response = send(
+        &app,
+        get("/contacts/validate-name?first_name=Ada&last_name=Lovelace&split=true"),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = body_text(response).await;
+    assert!(!body.contains("Looks Like a Full Name"));
+    assert!(body.contains(r#"<input id="first_name" name="first_name""#));
+    assert!(body.contains(r#"value="Ada" hx-swap-oob="true""#));
+    assert!(body.contains(r#"value="Lovelace" hx-swap-oob="true""#));
+}
+
+#[tokio::test]
+async fn mask_contact_details_in_the_list_only() {
+    let (app, _) = seeded_app(AppConfig {
+        mask_contact_details: true,
+        ..config()
+    })
+    .await;
+
+    let body = body_text(send(&app, get("/contacts?q=ada")).await).await;
+    assert!(body.contains("+* (***) ***-4567"));
+    assert!(body.contains("a***@example.com"));
+    assert!(!body.contains("ada@example.com"));
+    assert!(!body.contains("gravatar"));
+
+    let response = send(&app, get_json("/contacts?q=ada")).await;
+    let id = body_json(response).await["contacts"][0]["id"]
+        .as_u64()
+        .unwrap();
+    let body = body_text(send(&app, get(&format!("/contacts/{}", id))).await).await;
+    assert!(body.contains("Phone: +1 (555) 123-4567"));
+    assert!(body.contains("Email: ada@example.com"));
+}
+
+#[tokio::test]
+async fn fuzzy_search_tolerates_typos() {
+    let (app, _) = seeded_app(config()).await;
+    create_contact(&app, "John", "Backus", "", "john@example.com").await;
+
+    assert!(listed_emails(&app, "/contacts?q=Jhon").await.is_empty());
+    assert_eq!(
+        listed_emails(&app, "/contacts?q=Jhon&fuzzy=1").await,
+        ["john@example.com"],
+    );
+    assert_eq!(
+        listed_emails(&app, "/contacts?q=Jhon&fuzzy=1&initial=B").await,
+        ["john@example.com"],
+    );
+    assert!(listed_emails(&app, "/contacts?q=Jhon&fuzzy=1&initial=L")
+        .await
+        .is_empty());
+}
+
+#[tokio::test]
+async fn header_cells_without_text_are_labelled() {
+    let (app, _) = seeded_app(config()).await;
+
+    let body = body_text(send(&app, get("/contacts")).await).await;
+    let (_, thead) = body.split_once("<thead>").unwrap();
+    let (thead, _) = thead.split_once("</thead>").unwrap();
+    assert!(thead.contains(r#"<th class="js-only" aria-label="Select">"#));
+    assert!(thead.contains(r#"aria-label="Select All""#));
+    assert!(thead.contains(r#"<th aria-label="Avatar"></th>"#));
+    // Selecting all is wired up by htmx, without an inline event handler.
+    assert!(thead.contains("hx-on:change="));
+    assert!(!thead.contains("onchange="));
+}
+
+#[tokio::test]
+async fn demo_banner_until_dismissed() {
+    let (app, _) = build_app(AppConfig {
+        fake_contacts: 3,
+        ..config()
+    })
+    .await;
+
+    let body = body_text(send(&app, get("/contacts")).await).await;
+    assert!(body.contains(r#"<div id="demo-banner">"#));
+    assert!(body.contains("Showing demo data."));
+
+    let request = Request::get("/contacts")
+        .header(header::COOKIE, "other=1; demo_banner_dismissed=1")
+        .body(Body::empty())
+        .unwrap();
+    let body = body_text(send(&app, request).await).await;
+    assert!(!body.contains("demo-banner"));
+
+    // Real data has no banner to begin with.
+    let (app, _) = seeded_app(config()).await;
+    let body = body_text(send(&app, get("/contacts")).await).await;
+    assert!(!body.contains("demo-banner"));
+}
+
+#[tokio::test]
+async fn email_validation_knows_the_contact_being_edited() {
+    let (app, _) = seeded_app(config()).await;
+    let id = create_contact(&app, "Barbara", "Liskov", "", "barbara@example.com").await;
+
+    let body = body_text(send(&app, get("/contacts/new")).await).await;
+    assert!(body.contains(r#"hx-get="/contacts/validate-email""#));
+    assert!(!body.contains("hx-vals"));
+    assert!(!body.contains("contact_id"));
+
+    let vals = format!(r#"hx-vals="{{&quot;contact_id&quot;:{}}}""#, id);
+    for uri in [
+        format!("/contacts/{}/edit", id),
+        format!("/contacts/{}/edit?modal=1", id),
+    ] {
+        let body = body_text(send(&app, get(&uri)).await).await;
+        assert!(body.contains(&vals), "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn search() {
+    let (app, _) = seeded_app(config()).await;
+
+    assert_eq!(
+        listed_emails(&app, "/contacts?q=turing").await,
+        ["alan@example.com"],
+    );
+    assert_eq!(
+        listed_emails(&app, "/contacts?q=GRACE%40").await,
+        ["grace@example.com"],
+    );
+    assert!(listed_emails(&app, "/contacts?q=nobody").await.is_empty());
+    assert_eq!(
+        listed_emails(&app, "/contacts?q=%20%20").await.len(),
+        SEED_CONTACTS.len(),
+    );
+}
+
+#[tokio::test]
+async fn search_phone_as_displayed() {
+    let (app, _) = seeded_app(config()).await;
+
+    for q in [
+        "123-4567",
+        "(555)%20123-4567",
+        "555.123.4567",
+        "%2B15551234567",
+    ] {
+        assert_eq!(
+            listed_emails(&app, &format!("/contacts?q={}", q)).await,
+            ["ada@example.com"],
+            "{}",
+            q,
+        );
+    }
+    assert_eq!(
+        listed_emails(&app, "/contacts?q=987-65").await,
+        ["grace@example.com"],
+    );
+
+    // Counted the same way, for paging.
+    create_contact(
+        &app,
+        "Barbara",
+        "Liskov",
+        "555.123.4500",
+        "barbara@example.com",
+    )
+    .await;
+    let body = body_text(send(&app, get("/contacts?q=555-123-45&per_page=1")).await).await;
+    assert!(body.contains("Page 1 of 2"));
+}
+
+#[tokio::test]
+async fn paging() {
+    let (app, _) = seeded_app(AppConfig {
+        page_size: 2,
+        ..config()
+    })
+    .await;
+
+    let pages = [
+        listed_emails(&app, "/contacts?page=1").await,
+        listed_emails(&app, "/contacts?page=2").await,
+        listed_emails(&app, "/contacts?page=3").await,
+    ];
+    assert_eq!(pages.iter().map(Vec::len).collect::<Vec<_>>(), [2, 2, 1]);
+    let mut all: Vec<_> = pages.concat();
+    all.sort();
+    all.dedup();
+    assert_eq!(all.len(), SEED_CONTACTS.len());
+
+    // Pages past the last one are clamped to it.
+    assert_eq!(listed_emails(&app, "/contacts?page=9").await, pages[2]);
+
+    let body = body_text(send(&app, get("/contacts?page=2")).await).await;
+    assert!(body.contains("Page 2 of 3"));
+    assert!(body.contains("Previous"));
+    assert!(body.contains("Next"));
+    let body = body_text(send(&app, get("/contacts?per_page=5")).await).await;
+    assert!(body.contains("Page 1 of 1"));
+}
+
+#[tokio::test]
+async fn delete() {
+    let (app, _) = seeded_app(config()).await;
+    let ids: Vec<u32> = {
+        let response = send(&app, get_json("/contacts")).await;
+        body_json(response).await["contacts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|contact| contact["id"].as_u64().unwrap() as u32)
+            .collect()
+    };
+
+    let request = Request::delete(format!("/contacts/{}", ids[0]))
+        .header(header::ACCEPT, "application/json")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(send(&app, request).await.status(), StatusCode::NO_CONTENT);
+    let response = send(&app, get_json(&format!("/contacts/{}", ids[0]))).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let form = encode_form(&[
+        ("selected_contact_ids", &ids[1].to_string()),
+        ("selected_contact_ids", &ids[2].to_string()),
+    ]);
+    let request = Request::delete("/contacts")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(form))
+        .unwrap();
+    let response = send(&app, request).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(location(&response), "/contacts");
+
+    assert_eq!(
+        listed_emails(&app, "/contacts").await.len(),
+        SEED_CONTACTS.len() - 3,
+    );
+}
+
+#[tokio::test]
+async fn delete_missing_contact() {
+    let (app, _) = seeded_app(config()).await;
+    let id = create_contact(&app, "Barbara", "Liskov", "", "barbara@example.com").await;
+    let response = send(&app, post_form(&format!("/contacts/{}/delete", id), &[])).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+    // Neither a contact that never existed, nor one already deleted.
+    for id in [99999, id] {
+        let response = send(&app, post_form(&format!("/contacts/{}/delete", id), &[])).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let request = Request::delete(format!("/contacts/{}", id))
+            .header(header::ACCEPT, "application/json")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(send(&app, request).await.status(), StatusCode::NOT_FOUND);
+    }
+}
+
+#[tokio::test]
+async fn undo_deleting_selected_contacts() {
+    let (app, _) = seeded_app(config()).await;
+    let ids: Vec<u32> = {
+        let response = send(&app, get_json("/contacts?q=%40example")).await;
+        body_json(response).await["contacts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|contact| contact["id"].as_u64().unwrap() as u32)
+            .collect()
+    };
+    // Deleted before, so undoing the bulk delete must not bring it back.
+    let request = Request::delete(format!("/contacts/{}", ids[0]))
+        .header(header::ACCEPT, "application/json")
+        .body(Body::empty())
+        .unwrap();
+    send(&app, request).await;
+
+    let form = encode_form(&[
+        ("selected_contact_ids", &ids[0].to_string()),
+        ("selected_contact_ids", &ids[1].to_string()),
+        ("selected_contact_ids", &ids[2].to_string()),
+    ]);
+    let request = Request::delete("/contacts")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(form))
+        .unwrap();
+    let response = send(&app, request).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+    let request = Request::get("/contacts")
+        .header(header::COOKIE, cookies(&response))
+        .body(Body::empty())
+        .unwrap();
+    let body = body_text(send(&app, request).await).await;
+    assert!(body.contains("Deleted 2 Contacts!"));
+    assert!(body.contains(r#"action="/contacts/restore""#));
+    let undo: Vec<_> = body
+        .split(r#"name="contact_ids" value=""#)
+        .skip(1)
+        .map(|rest| rest.split_once('"').unwrap().0)
+        .collect();
+    assert_eq!(undo, [ids[1].to_string(), ids[2].to_string()]);
+
+    let form: Vec<_> = undo.iter().map(|id| ("contact_ids", *id)).collect();
+    let response = send(&app, post_form("/contacts/restore", &form)).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(location(&response), "/contacts");
+
+    assert_eq!(
+        listed_emails(&app, "/contacts").await.len(),
+        SEED_CONTACTS.len() - 1,
+    );
+    let response = send(&app, get_json(&format!("/contacts/{}", ids[0]))).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn repeating_a_bulk_delete_deletes_none() {
+    let (app, _) = seeded_app(config()).await;
+    let ids: Vec<u32> = {
+        let response = send(&app, get_json("/contacts?q=%40example")).await;
+        body_json(response).await["contacts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|contact| contact["id"].as_u64().unwrap() as u32)
+            .collect()
+    };
+    let delete_selected = || {
+        let form = encode_form(&[
+            ("selected_contact_ids", &ids[0].to_string()),
+            ("selected_contact_ids", &ids[1].to_string()),
+        ]);
+        Request::delete("/contacts")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(form))
+            .unwrap()
+    };
+    let flash_after = |response: &axum::response::Response| {
+        Request::get("/contacts")
+            .header(header::COOKIE, cookies(response))
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = send(&app, delete_selected()).await;
+    let body = body_text(send(&app, flash_after(&response)).await).await;
+    assert!(body.contains("Deleted 2 Contacts!"));
+
+    let response = send(&app, delete_selected()).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let body = body_text(send(&app, flash_after(&response)).await).await;
+    assert!(body.contains("Deleted 0 Contacts!"));
+    // There is nothing to undo.
+    assert!(!body.contains(r#"action="/contacts/restore""#));
+    assert_eq!(
+        listed_emails(&app, "/contacts").await.len(),
+        SEED_CONTACTS.len() - 2,
+    );
+}
+
+#[tokio::test]
+async fn undo_deleting_a_row() {
+    let (app, _) = seeded_app(config()).await;
+    let id = create_contact(&app, "Barbara", "Liskov", "", "barbara@example.com").await;
+
+    let request = Request::delete(format!("/contacts/{}", id))
+        .header("HX-Request", "true")
+        .body(Body::empty())
+        .unwrap();
+    let response = send(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = body_text(response).await;
+    // Only the flash, which htmx swaps in out of band, leaving the row empty.
+    assert!(body.starts_with(r#"<div id="flashes" hx-swap-oob="true">"#));
+    assert!(body.contains("Deleted Contact!"));
+    let action = format!("/contacts/{}/restore", id);
+    assert!(body.contains(&format!(r#"action="{}""#, action)));
+
+    let response = send(&app, post_form(&action, &[])).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let response = send(&app, get_json(&format!("/contacts/{}", id))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn index_counts_contacts_by_last_name_initial() {
+    let (app, _) = seeded_app(config()).await;
+    create_contact(&app, "Leslie", "Lamport", "", "leslie@example.com").await;
+    create_contact(&app, "Someone", "42", "", "someone@example.com").await;
+
+    let body = body_text(send(&app, get("/contacts/index")).await).await;
+    let counts: Vec<(String, u32)> = body
+        .split("<a ")
+        .skip(2)
+        .map(|link| {
+            let (_, rest) = link.split_once('>').unwrap();
+            let (initial, rest) = rest.split_once("<sup>").unwrap();
+            let (count, _) = rest.split_once("</sup>").unwrap();
+            (initial.to_string(), count.parse().unwrap())
+        })
+        .collect();
+    // "Álvarez" is under A, accents being ignored.
+    assert_eq!(
+        counts,
+        [
+            ("A".to_string(), 1),
+            ("D".to_string(), 1),
+            ("H".to_string(), 1),
+            ("L".to_string(), 2),
+            ("T".to_string(), 1),
+            ("#".to_string(), 1),
+        ],
+    );
+    assert!(body.contains(r#"href="/contacts?page=1&amp;initial=L""#));
+}
+
+#[tokio::test]
+async fn initial_filter() {
+    let (app, _) = seeded_app(config()).await;
+    create_contact(&app, "Leslie", "Lamport", "", "leslie@example.com").await;
+
+    let mut emails = listed_emails(&app, "/contacts?initial=L").await;
+    emails.sort();
+    assert_eq!(emails, ["ada@example.com", "leslie@example.com"]);
+    assert_eq!(listed_emails(&app, "/contacts?initial=l").await.len(), 2,);
+    assert_eq!(
+        listed_emails(&app, "/contacts?initial=A").await,
+        ["jose@example.com"],
+    );
+    assert!(listed_emails(&app, "/contacts?initial=%23")
+        .await
+        .is_empty());
+    assert_eq!(
+        listed_emails(&app, "/contacts?initial=L&q=leslie").await,
+        ["leslie@example.com"],
+    );
+    assert!(listed_emails(&app, "/contacts?initial=Z").await.is_empty());
+}
+
+#[tokio::test]
+async fn index_placeholder_leaves_the_list_alone() {
+    let (app, _) = seeded_app(config()).await;
+
+    let body = body_text(send(&app, get("/contacts")).await).await;
+    // The placeholder replaces itself with the index once loaded, so the list
+    // must not be inside it.
+    let placeholder = body.find(r#"hx-get="/contacts/index""#).unwrap();
+    let list = body.find(r#"<div id="contacts-list">"#).unwrap();
+    assert!(body[placeholder..list].ends_with("></div>"));
+}
+
+fn import_request(body: &str) -> Request<Body> {
+    Request::post("/contacts/import")
+        .header(
+            header::CONTENT_TYPE,
+            "multipart/form-data; boundary=BOUNDARY",
+        )
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn import() {
+    let (app, _) = seeded_app(config()).await;
+
+    let body = "--BOUNDARY\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"contacts.csv\"\r\n\
+        Content-Type: text/csv\r\n\r\n\
+        first,last,phone,email\r\n\
+        Barbara,Liskov,,barbara@example.com\r\n\
+        Ada,Again,,ada@example.com\r\n\
+        \r\n--BOUNDARY--\r\n";
+    let response = send(&app, import_request(body)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = body_text(response).await;
+    assert!(body.contains("1 imported, 1 skipped"));
+    assert!(body.contains("Row 2: Email Must Be Unique"));
+}
+
+#[tokio::test]
+async fn import_malformed_upload() {
+    let (app, _) = seeded_app(config()).await;
+
+    // Cut off before the closing boundary.
+    let body = "--BOUNDARY\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"contacts.csv\"\r\n\r\n\
+        first,last,phone,email\r\n";
+    let response = send(&app, import_request(body)).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(body_text(response)
+        .await
+        .contains("Could Not Read the Upload"));
+
+    assert_eq!(
+        listed_emails(&app, "/contacts").await.len(),
+        SEED_CONTACTS.len(),
+    );
+}
+
+#[tokio::test]
+async fn import_too_many_rows() {
+    let (app, _) = seeded_app(AppConfig {
+        import_row_limit: 2,
+        ..config()
+    })
+    .await;
+
+    let body = "--BOUNDARY\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"contacts.csv\"\r\n\
+        Content-Type: text/csv\r\n\r\n\
+        first,last,phone,email\r\n\
+        Barbara,Liskov,,barbara@example.com\r\n\
+        Leslie,Lamport,,leslie@example.com\r\n\
+        Tony,Hoare,,tony@example.com\r\n\
+        \r\n--BOUNDARY--\r\n";
+    let response = send(&app, import_request(body)).await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert!(body_text(response).await.contains("At Most 2"));
+
+    // Not even the rows within the limit.
+    assert_eq!(
+        listed_emails(&app, "/contacts").await.len(),
+        SEED_CONTACTS.len(),
+    );
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request},
+    response::Response,
+    Router,
+};
+
+use common::*;
+
+fn content_type(response: &Response) -> &str {
+    response.headers()[header::CONTENT_TYPE].to_str().unwrap()
+}
+
+async fn htmx_get(app: &Router, uri: &str, trigger: &str) -> Response {
+    let request = Request::get(uri)
+        .header("HX-Request", "true")
+        .header("HX-Trigger", trigger)
+        .body(Body::empty())
+        .unwrap();
+    send(app, request).await
+}
+
+#[tokio::test]
+async fn full_page_is_utf8() {
+    let (app, _) = seeded_app(config()).await;
+
+    for uri in [
+        "/contacts",
+        "/contacts/new",
+        "/contacts/trash",
+        "/contacts/nobody",
+    ] {
+        let response = send(&app, get(uri)).await;
+        assert_eq!(
+            content_type(&response),
+            "text/html; charset=utf-8",
+            "{}",
+            uri
+        );
+        assert!(body_text(response).await.contains("<html"), "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn htmx_fragment_is_utf8() {
+    let (app, _) = seeded_app(config()).await;
+
+    let response = htmx_get(&app, "/contacts?q=jos%C3%A9", "search").await;
+    assert_eq!(content_type(&response), "text/html; charset=utf-8");
+    let body = body_text(response).await;
+    assert!(!body.contains("<html"));
+    assert!(body.contains("José"));
+
+    for uri in ["/contacts/count", "/contacts/index", "/contacts/archive"] {
+        let response = htmx_get(&app, uri, "").await;
+        assert_eq!(
+            content_type(&response),
+            "text/html; charset=utf-8",
+            "{}",
+            uri
+        );
+    }
+}
+
+#[tokio::test]
+async fn exports_are_utf8() {
+    let (app, _) = seeded_app(config()).await;
+
+    for (uri, expected) in [
+        ("/contacts/export.json", "application/json; charset=utf-8"),
+        (
+            "/api/contacts/export.ndjson",
+            "application/x-ndjson; charset=utf-8",
+        ),
+        ("/contacts/export.md", "text/markdown; charset=utf-8"),
+    ] {
+        let response = send(&app, get(uri)).await;
+        assert_eq!(content_type(&response), expected, "{}", uri);
+        assert!(body_text(response).await.contains("José"), "{}", uri);
+    }
+}
@@ -0,0 +1,122 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+
+use contact::config::{AppConfig, Environment};
+
+use common::*;
+
+fn production() -> AppConfig {
+    AppConfig {
+        environment: Environment::Production,
+        behind_proxy: true,
+        ..config()
+    }
+}
+
+fn forwarded_over_http(uri: &str) -> Request<Body> {
+    Request::get(uri)
+        .header(header::HOST, "contacts.example.com")
+        .header("X-Forwarded-Proto", "http")
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn plain_http_is_redirected_to_https_in_production() {
+    let (app, _) = seeded_app(production()).await;
+
+    let response = send(&app, forwarded_over_http("/contacts?q=ada&page=1")).await;
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(
+        location(&response),
+        "https://contacts.example.com/contacts?q=ada&page=1"
+    );
+
+    let request = Request::get("/contacts")
+        .header(header::HOST, "contacts.example.com")
+        .header("X-Forwarded-Proto", "https")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(send(&app, request).await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn forwarded_proto_is_ignored_unless_behind_a_proxy() {
+    for config in [
+        config(),
+        AppConfig {
+            behind_proxy: false,
+            ..production()
+        },
+    ] {
+        let (app, _) = seeded_app(config).await;
+        let response = send(&app, forwarded_over_http("/contacts")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
+
+/// The `Set-Cookie` of the flash that deleting a contact leaves, split into
+/// its attributes.
+async fn flash_cookie_attributes(app: &Router) -> Vec<String> {
+    let id = create_contact(app, "Barbara", "Liskov", "", "barbara@example.com").await;
+    let response = send(app, post_form(&format!("/contacts/{}/delete", id), &[])).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let set_cookie = response
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .map(|cookie| cookie.to_str().unwrap())
+        .find(|cookie| cookie.starts_with("axum-flash="))
+        .unwrap();
+    set_cookie
+        .split(';')
+        .map(|attribute| attribute.trim().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn flash_cookie_is_lax_and_only_secure_in_production() {
+    for (config, secure) in [(config(), false), (production(), true)] {
+        let (app, _) = seeded_app(config).await;
+        let attributes = flash_cookie_attributes(&app).await;
+        assert!(
+            attributes.iter().any(|a| a == "SameSite=Lax"),
+            "{:?}",
+            attributes
+        );
+        assert!(
+            !attributes.iter().any(|a| a == "SameSite=Strict"),
+            "{:?}",
+            attributes
+        );
+        assert_eq!(
+            attributes.iter().any(|a| a == "Secure"),
+            secure,
+            "{:?}",
+            attributes
+        );
+    }
+}
+
+#[tokio::test]
+async fn tls_only_features_are_only_on_in_production() {
+    for (config, production) in [(config(), false), (production(), true)] {
+        let (app, _) = seeded_app(config).await;
+
+        let response = send(&app, get("/contacts")).await;
+        assert_eq!(
+            response
+                .headers()
+                .get(header::CONTENT_SECURITY_POLICY)
+                .map(|csp| csp.to_str().unwrap()),
+            production.then_some("upgrade-insecure-requests"),
+        );
+        let attributes = flash_cookie_attributes(&app).await;
+        assert_eq!(attributes.iter().any(|a| a == "Secure"), production);
+    }
+}
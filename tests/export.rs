@@ -0,0 +1,80 @@
+mod common;
+
+use axum::http::{header, StatusCode};
+
+use contact::config::AppConfig;
+
+use common::*;
+
+#[tokio::test]
+async fn ndjson_export_has_a_contact_per_line() {
+    let (app, _) = seeded_app(config()).await;
+    let id = create_contact(&app, "Barbara", "Liskov", "", "barbara@example.com").await;
+    send(&app, post_form(&format!("/contacts/{}/delete", id), &[])).await;
+
+    let response = send(&app, get("/api/contacts/export.ndjson")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = body_text(response).await;
+    assert!(body.ends_with('\n'));
+    let contacts: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(contacts.len(), SEED_CONTACTS.len());
+    let mut emails: Vec<_> = contacts
+        .iter()
+        .map(|contact| {
+            assert!(contact["id"].is_u64());
+            for field in ["first", "last", "phone"] {
+                assert!(contact[field].is_string(), "{}", field);
+            }
+            contact["email"].as_str().unwrap()
+        })
+        .collect();
+    emails.sort();
+    let mut expected: Vec<_> = SEED_CONTACTS.iter().map(|(.., email)| *email).collect();
+    expected.sort();
+    assert_eq!(emails, expected);
+
+    let body = body_text(send(&app, get("/api/contacts/export.ndjson?q=ada")).await).await;
+    assert_eq!(body.lines().count(), 1);
+}
+
+#[tokio::test]
+async fn markdown_export_is_a_table_of_the_contacts() {
+    let (app, _) = seeded_app(config()).await;
+    create_contact(&app, "Pipe | Name", "Back\\slash", "", "pipe@example.com").await;
+
+    let body = body_text(send(&app, get("/contacts/export.md")).await).await;
+    let lines: Vec<_> = body.lines().collect();
+    assert_eq!(lines[0], "| First | Last | Phone | Email |");
+    assert_eq!(lines[1], "| --- | --- | --- | --- |");
+    assert_eq!(lines.len(), 2 + SEED_CONTACTS.len() + 1);
+    assert!(lines.contains(&"| Ada | Lovelace | +15551234567 | ada@example.com |"));
+    assert!(lines.contains(&r"| Pipe \| Name | Back\\slash |  | pipe@example.com |"));
+}
+
+#[tokio::test]
+async fn json_export_has_every_contact_not_in_the_trash() {
+    let (app, _) = seeded_app(AppConfig {
+        archive_file_name: "archive-{date}.{ext}".to_string(),
+        ..config()
+    })
+    .await;
+    let id = create_contact(&app, "Barbara", "Liskov", "", "barbara@example.com").await;
+    send(&app, post_form(&format!("/contacts/{}/delete", id), &[])).await;
+
+    let response = send(&app, get("/contacts/export.json")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let disposition = response.headers()[header::CONTENT_DISPOSITION]
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(disposition.starts_with(r#"attachment; filename="contacts-export-"#));
+    assert!(disposition.ends_with(r#".json""#));
+    let exported = body_json(response).await;
+
+    let listed = body_json(send(&app, get_json("/contacts?per_page=100")).await).await;
+    assert_eq!(exported, listed["contacts"]);
+    assert_eq!(exported.as_array().unwrap().len(), SEED_CONTACTS.len());
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+
+use common::*;
+
+#[tokio::test]
+async fn health() {
+    let (app, app_state) = seeded_app(config()).await;
+
+    for uri in ["/health", "/healthz"] {
+        let response = send(&app, get(uri)).await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+        assert_eq!(
+            body_json(response).await,
+            serde_json::json!({ "status": "ok" })
+        );
+    }
+
+    app_state.shut_down(Duration::from_secs(1)).await;
+    for uri in ["/health", "/healthz"] {
+        let response = send(&app, get(uri)).await;
+        assert_eq!(
+            response.status(),
+            StatusCode::SERVICE_UNAVAILABLE,
+            "{}",
+            uri
+        );
+        assert_eq!(
+            body_json(response).await,
+            serde_json::json!({ "status": "error", "error": "pool_closed" })
+        );
+    }
+}
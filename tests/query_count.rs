@@ -0,0 +1,52 @@
+mod common;
+
+use axum::{body::Body, http::Request, Router};
+
+use contact::{config::AppConfig, AppState};
+
+use common::*;
+
+/// The most queries the list page may make, however many contacts there are.
+const MAX_LIST_QUERIES: usize = 3;
+
+async fn queries_made(app: &Router, app_state: &AppState, request: Request<Body>) -> usize {
+    let before = app_state.query_count().unwrap();
+    body_text(send(app, request).await).await;
+    app_state.query_count().unwrap() - before
+}
+
+#[tokio::test]
+async fn list_page_makes_a_bounded_number_of_queries() {
+    let (app, app_state) = seeded_app(AppConfig {
+        count_queries: true,
+        ..config()
+    })
+    .await;
+
+    let few = queries_made(&app, &app_state, get("/contacts")).await;
+    assert!(few > 0);
+    assert!(few <= MAX_LIST_QUERIES, "{} queries", few);
+
+    for i in 0..30 {
+        let email = format!("more{}@example.com", i);
+        create_contact(&app, "More", "Contacts", "", &email).await;
+    }
+    let many = queries_made(&app, &app_state, get("/contacts")).await;
+    assert_eq!(many, few);
+
+    for uri in [
+        "/contacts?q=more",
+        "/contacts?initial=C",
+        "/contacts?page=2",
+    ] {
+        let queries = queries_made(&app, &app_state, get(uri)).await;
+        assert!(queries <= MAX_LIST_QUERIES, "{}: {} queries", uri, queries);
+    }
+}
+
+#[tokio::test]
+async fn queries_are_not_counted_unless_asked_to() {
+    let (_, app_state) = seeded_app(config()).await;
+
+    assert_eq!(app_state.query_count(), None);
+}
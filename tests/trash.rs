@@ -0,0 +1,166 @@
+mod common;
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+
+use common::*;
+
+async fn count_text(app: &Router) -> String {
+    body_text(send(app, get("/contacts/count")).await).await
+}
+
+fn count(n: usize) -> String {
+    format!("({} total Contacts)", n)
+}
+
+#[tokio::test]
+async fn trashed_contacts_are_left_out_of_the_list_and_count() {
+    let (app, _) = seeded_app(config()).await;
+    // Counted once, so that a stale count would be served from now on.
+    assert_eq!(count_text(&app).await, count(SEED_CONTACTS.len()));
+
+    let ada = create_contact(&app, "Ada", "Byron", "", "byron@example.com").await;
+    let response = send(&app, post_form(&format!("/contacts/{}/delete", ada), &[])).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+    let emails = listed_emails(&app, "/contacts").await;
+    assert_eq!(emails.len(), SEED_CONTACTS.len());
+    assert!(!emails.contains(&"byron@example.com".to_string()));
+    assert!(listed_emails(&app, "/contacts?q=byron").await.is_empty());
+    assert!(listed_emails(&app, "/contacts?initial=B").await.is_empty());
+    assert_eq!(count_text(&app).await, count(SEED_CONTACTS.len()));
+    let body = body_text(send(&app, get("/contacts/index")).await).await;
+    assert!(!body.contains("initial=B"));
+
+    let body = body_text(send(&app, get("/contacts/trash")).await).await;
+    assert!(body.contains("byron@example.com"));
+}
+
+#[tokio::test]
+async fn count_follows_deleting_restoring_and_purging() {
+    let (app, _) = seeded_app(config()).await;
+    let ids: Vec<u32> = {
+        let response = send(&app, get_json("/contacts")).await;
+        body_json(response).await["contacts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|contact| contact["id"].as_u64().unwrap() as u32)
+            .collect()
+    };
+    assert_eq!(count_text(&app).await, count(SEED_CONTACTS.len()));
+
+    send(
+        &app,
+        post_form(&format!("/contacts/{}/delete", ids[0]), &[]),
+    )
+    .await;
+    assert_eq!(count_text(&app).await, count(SEED_CONTACTS.len() - 1));
+
+    let form = encode_form(&[
+        ("selected_contact_ids", &ids[1].to_string()),
+        ("selected_contact_ids", &ids[2].to_string()),
+    ]);
+    let request = Request::delete("/contacts")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(form))
+        .unwrap();
+    send(&app, request).await;
+    assert_eq!(count_text(&app).await, count(SEED_CONTACTS.len() - 3));
+
+    send(
+        &app,
+        post_form(&format!("/contacts/{}/restore", ids[0]), &[]),
+    )
+    .await;
+    assert_eq!(count_text(&app).await, count(SEED_CONTACTS.len() - 2));
+
+    let form = [
+        ("contact_ids", ids[1].to_string()),
+        ("contact_ids", ids[2].to_string()),
+    ];
+    let form: Vec<_> = form.iter().map(|(k, v)| (*k, v.as_str())).collect();
+    send(&app, post_form("/contacts/restore", &form)).await;
+    assert_eq!(count_text(&app).await, count(SEED_CONTACTS.len()));
+
+    send(
+        &app,
+        post_form(&format!("/contacts/{}/delete", ids[0]), &[]),
+    )
+    .await;
+    let request = Request::delete("/contacts/trash")
+        .body(Body::empty())
+        .unwrap();
+    let response = send(&app, request).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(count_text(&app).await, count(SEED_CONTACTS.len() - 1));
+}
+
+#[tokio::test]
+async fn purging_frees_emails() {
+    let (app, _) = seeded_app(config()).await;
+    let id = create_contact(&app, "Barbara", "Liskov", "", "barbara@example.com").await;
+    send(&app, post_form(&format!("/contacts/{}/delete", id), &[])).await;
+
+    let request = Request::delete("/contacts/trash")
+        .body(Body::empty())
+        .unwrap();
+    send(&app, request).await;
+    let body = body_text(send(&app, get("/contacts/trash")).await).await;
+    assert!(!body.contains("barbara@example.com"));
+
+    // Fails if the email were still taken.
+    let new_id = create_contact(&app, "Barbara", "Liskov", "", "barbara@example.com").await;
+    assert_ne!(new_id, id);
+    // The purged contact is gone for good.
+    let response = send(&app, post_form(&format!("/contacts/{}/restore", id), &[])).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(
+        listed_emails(&app, "/contacts?q=barbara").await,
+        ["barbara@example.com"],
+    );
+}
+
+#[tokio::test]
+async fn count_follows_creating_and_importing() {
+    let (app, _) = seeded_app(config()).await;
+    assert_eq!(count_text(&app).await, count(SEED_CONTACTS.len()));
+
+    create_contact(&app, "Barbara", "Liskov", "", "barbara@example.com").await;
+    assert_eq!(count_text(&app).await, count(SEED_CONTACTS.len() + 1));
+
+    let response = send(
+        &app,
+        post_form(
+            "/contacts/new",
+            &[
+                ("first_name", "Leslie"),
+                ("last_name", "Lamport"),
+                ("phone", ""),
+                ("email", "leslie@example.com"),
+            ],
+        ),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(count_text(&app).await, count(SEED_CONTACTS.len() + 2));
+
+    let body = "--BOUNDARY\r\n\
+        Content-Disposition: form-data; name=\"file\"; filename=\"contacts.csv\"\r\n\
+        Content-Type: text/csv\r\n\r\n\
+        first,last,phone,email\r\n\
+        Tony,Hoare,,tony@example.com\r\n\
+        \r\n--BOUNDARY--\r\n";
+    let request = Request::post("/contacts/import")
+        .header(
+            header::CONTENT_TYPE,
+            "multipart/form-data; boundary=BOUNDARY",
+        )
+        .body(Body::from(body))
+        .unwrap();
+    assert_eq!(send(&app, request).await.status(), StatusCode::OK);
+    assert_eq!(count_text(&app).await, count(SEED_CONTACTS.len() + 3));
+}